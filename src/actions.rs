@@ -0,0 +1,45 @@
+// secondary actions a plugin can offer on a result, e.g. "copy path" or "reveal in folder", and what the host should do after running one
+
+use abi_stable::{std_types::RString, StableAbi};
+use serde::{Deserialize, Serialize};
+
+// action id the host uses for the default action of a result, plugins dont need to list it
+pub const PRIMARY_ACTION: &str = "primary";
+
+#[repr(C)]
+#[derive(StableAbi, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResultAction {
+    // passed back to the plugin in execute_action
+    pub id: RString,
+    // shown to the user
+    pub name: RString,
+}
+
+impl ResultAction {
+    pub fn new(id: &str, name: &str) -> Self {
+        Self { id: id.into(), name: name.into() }
+    }
+    pub fn is_primary(&self) -> bool {
+        self.id == PRIMARY_ACTION
+    }
+}
+
+// what the host should do once an action has run
+#[repr(C)]
+#[derive(StableAbi, Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum ExecuteOutcome {
+    // hide the launcher, what execute always did before
+    #[default]
+    Close,
+    KeepOpen,
+    // replace the query text, e.g. to drill down into a folder
+    SetQuery {
+        query: RString,
+    },
+    // the action failed, show the message to the user and keep the launcher open
+    ShowError {
+        message: RString,
+    },
+    // the action changed something, search again with the current query
+    RefreshResults,
+}
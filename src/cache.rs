@@ -0,0 +1,224 @@
+// opt-in result cache in front of plugin searches, typing quickly sends nearly the same query over and over
+// plugins decide whether their results can be cached, for how long, and whether results for "foo" can be filtered down for "foob"
+
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use abi_stable::StableAbi;
+use serde::{Deserialize, Serialize};
+
+use crate::{fuzzy_match, PluginId, Query, SearchResult};
+
+#[repr(C)]
+#[derive(StableAbi, Clone, Copy, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct CachePolicy {
+    pub cacheable: bool,
+    // how long results stay valid, 0 means they never expire
+    pub ttl_ms: u64,
+    // results for a query are a superset of the results for any longer query starting with it,
+    // so the host can fuzzy filter cached results instead of searching again
+    pub prefix_refinable: bool,
+}
+
+impl CachePolicy {
+    pub fn cacheable(ttl: Duration) -> Self {
+        Self {
+            cacheable: true,
+            ttl_ms: ttl.as_millis() as u64,
+            prefix_refinable: false,
+        }
+    }
+    pub fn prefix_refinable(mut self) -> Self {
+        self.prefix_refinable = true;
+        self
+    }
+    fn is_expired(&self, inserted: Instant) -> bool {
+        self.ttl_ms != 0 && inserted.elapsed() > Duration::from_millis(self.ttl_ms)
+    }
+}
+
+struct CacheEntry {
+    results: Vec<SearchResult>,
+    inserted: Instant,
+    last_used: u64,
+}
+
+// PluginId filename, routing keyword and query text
+type CacheKey = (String, Option<String>, String);
+
+fn cache_key(plugin_id: &PluginId, query: &Query) -> CacheKey {
+    (plugin_id.filename.to_string(), query.keyword().map(str::to_owned), query.text().to_owned())
+}
+
+// least recently used cache keyed by PluginId filename, routing keyword and query text
+pub struct ResultCache {
+    capacity: usize,
+    entries: HashMap<CacheKey, CacheEntry>,
+    // bumped on every access, used to find the least recently used entry
+    tick: u64,
+}
+
+impl ResultCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            entries: HashMap::new(),
+            tick: 0,
+        }
+    }
+    // exact match first, then the longest cached prefix of the query if the plugin allows refining
+    // refined results are scored and highlighted again with fuzzy_match against the longer query
+    pub fn get(&mut self, plugin_id: &PluginId, query: &Query, policy: &CachePolicy) -> Option<Vec<SearchResult>> {
+        if !policy.cacheable {
+            return None;
+        }
+        let key = cache_key(plugin_id, query);
+        self.entries.retain(|(p, _, _), entry| *p != key.0 || !policy.is_expired(entry.inserted));
+        self.tick += 1;
+        if let Some(entry) = self.entries.get_mut(&key) {
+            entry.last_used = self.tick;
+            return Some(entry.results.clone());
+        }
+        if !policy.prefix_refinable {
+            return None;
+        }
+        let (_, entry) = self
+            .entries
+            .iter_mut()
+            .filter(|((p, keyword, text), _)| *p == key.0 && *keyword == key.1 && query.text().starts_with(text.as_str()))
+            .max_by_key(|((_, _, text), _)| text.len())?;
+        entry.last_used = self.tick;
+        Some(
+            entry
+                .results
+                .iter()
+                .filter_map(|r| fuzzy_match(query.text(), r.title()).map(|m| m.apply(r.clone())))
+                .collect(),
+        )
+    }
+    // results that reached the query's max_results arent cached, the plugin may have stopped early so they could be missing matches
+    pub fn insert(&mut self, plugin_id: &PluginId, query: &Query, policy: &CachePolicy, results: Vec<SearchResult>) {
+        if !policy.cacheable || query.max_results().is_some_and(|max_results| results.len() >= max_results as usize) {
+            return;
+        }
+        let key = cache_key(plugin_id, query);
+        if !self.entries.contains_key(&key) && self.entries.len() >= self.capacity {
+            if let Some(oldest) = self.entries.iter().min_by_key(|(_, entry)| entry.last_used).map(|(key, _)| key.clone()) {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.tick += 1;
+        self.entries.insert(
+            key,
+            CacheEntry {
+                results,
+                inserted: Instant::now(),
+                last_used: self.tick,
+            },
+        );
+    }
+    // drops every cached result for the plugin, e.g. after it was reloaded
+    pub fn invalidate(&mut self, plugin_id: &PluginId) {
+        self.entries.retain(|(p, _, _), _| p.as_str() != plugin_id.filename.as_ref());
+    }
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id() -> PluginId {
+        PluginId { filename: "files".into() }
+    }
+
+    fn titles(results: &[SearchResult]) -> Vec<&str> {
+        results.iter().map(SearchResult::title).collect()
+    }
+
+    #[test]
+    fn only_caches_cacheable_plugins() {
+        let mut cache = ResultCache::new(4);
+        let query = Query::new("a");
+        cache.insert(&id(), &query, &CachePolicy::default(), vec![SearchResult::new("a")]);
+        assert!(cache.is_empty());
+        assert_eq!(cache.get(&id(), &query, &CachePolicy::default()), None);
+        let policy = CachePolicy::cacheable(Duration::ZERO);
+        cache.insert(&id(), &query, &policy, vec![SearchResult::new("a")]);
+        assert_eq!(cache.get(&id(), &query, &policy).as_deref().map(titles), Some(vec!["a"]));
+    }
+
+    #[test]
+    fn keyword_is_part_of_the_key() {
+        let mut cache = ResultCache::new(4);
+        let policy = CachePolicy::cacheable(Duration::ZERO).prefix_refinable();
+        cache.insert(&id(), &Query::new("ab").set_keyword(Some("f")), &policy, vec![SearchResult::new("ab")]);
+        assert_eq!(cache.get(&id(), &Query::new("ab"), &policy), None);
+        assert_eq!(cache.get(&id(), &Query::new("abc"), &policy), None);
+        assert!(cache.get(&id(), &Query::new("ab").set_keyword(Some("f")), &policy).is_some());
+    }
+
+    #[test]
+    fn refines_longest_prefix_and_rescores() {
+        let mut cache = ResultCache::new(4);
+        let policy = CachePolicy::cacheable(Duration::ZERO).prefix_refinable();
+        cache.insert(&id(), &Query::new("r"), &policy, vec![SearchResult::new("nothing")]);
+        cache.insert(
+            &id(),
+            &Query::new("re"),
+            &policy,
+            vec![SearchResult::new("report.pdf").set_score(-1.0), SearchResult::new("readme"), SearchResult::new("rename")],
+        );
+        let refined = cache.get(&id(), &Query::new("rep"), &policy).unwrap();
+        assert_eq!(titles(&refined), vec!["report.pdf"]);
+        let expected = fuzzy_match("rep", "report.pdf").unwrap();
+        assert_eq!(refined[0].score(), expected.score as f64);
+        assert_eq!(refined[0].highlights(), expected.ranges.as_slice());
+        assert_eq!(cache.get(&id(), &Query::new("rep"), &CachePolicy::cacheable(Duration::ZERO)), None);
+    }
+
+    #[test]
+    fn skips_results_cut_off_at_max_results() {
+        let mut cache = ResultCache::new(4);
+        let policy = CachePolicy::cacheable(Duration::ZERO);
+        let query = Query::new("a").set_max_results(Some(2));
+        cache.insert(&id(), &query, &policy, vec![SearchResult::new("a1"), SearchResult::new("a2")]);
+        assert!(cache.is_empty());
+        cache.insert(&id(), &query, &policy, vec![SearchResult::new("a1")]);
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let mut cache = ResultCache::new(2);
+        let policy = CachePolicy::cacheable(Duration::ZERO);
+        cache.insert(&id(), &Query::new("a"), &policy, Vec::new());
+        cache.insert(&id(), &Query::new("b"), &policy, Vec::new());
+        cache.get(&id(), &Query::new("a"), &policy);
+        cache.insert(&id(), &Query::new("c"), &policy, Vec::new());
+        assert!(cache.get(&id(), &Query::new("a"), &policy).is_some());
+        assert!(cache.get(&id(), &Query::new("b"), &policy).is_none());
+        cache.invalidate(&id());
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn expired_entries_are_dropped() {
+        let mut cache = ResultCache::new(2);
+        let policy = CachePolicy::cacheable(Duration::from_millis(1));
+        cache.insert(&id(), &Query::new("a"), &policy, Vec::new());
+        std::thread::sleep(Duration::from_millis(5));
+        assert_eq!(cache.get(&id(), &Query::new("a"), &policy), None);
+        assert!(cache.is_empty());
+    }
+}
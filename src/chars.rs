@@ -1,8 +1,11 @@
 use abi_stable::{std_types::RVec, StableAbi};
+use serde::{Deserialize, Serialize};
+
+use crate::HighlightRange;
 use std::fmt::{Debug, Display, Formatter, Result};
 
 #[repr(C)]
-#[derive(StableAbi)]
+#[derive(StableAbi, Clone, Copy, Serialize, Deserialize)]
 pub struct ColoredChar {
     char: u32,
     color: u32,
@@ -27,13 +30,15 @@ impl ColoredChar {
         Self { char: char as u32, color }
     }
     pub fn new_rgba(char: char, r: u8, g: u8, b: u8, a: u8) -> Self {
-        Self::new(char, {
-            let r = (r as u32) << 24;
-            let g = (g as u32) << 16;
-            let b = (b as u32) << 8;
-            let a = a as u32;
-            r | g | b | a
-        })
+        Self::new(char, Self::pack_rgba(r, g, b, a))
+    }
+    // the color layout used by new_rgba, also used for EntryType::Color
+    pub fn pack_rgba(r: u8, g: u8, b: u8, a: u8) -> u32 {
+        let r = (r as u32) << 24;
+        let g = (g as u32) << 16;
+        let b = (b as u32) << 8;
+        let a = a as u32;
+        r | g | b | a
     }
     pub fn char(&self) -> char {
         std::char::from_u32(self.char).unwrap()
@@ -44,4 +49,11 @@ impl ColoredChar {
     pub fn from_string(s: &str, color: u32) -> RVec<ColoredChar> {
         s.chars().map(|c| ColoredChar::new(c, color)).collect()
     }
+    // colors the characters inside any of the ranges with highlight_color, e.g. from SearchResult::highlights
+    pub fn from_string_highlighted(s: &str, color: u32, highlight_color: u32, ranges: &[HighlightRange]) -> RVec<ColoredChar> {
+        s.chars()
+            .enumerate()
+            .map(|(i, c)| ColoredChar::new(c, if ranges.iter().any(|r| r.contains(i as u32)) { highlight_color } else { color }))
+            .collect()
+    }
 }
@@ -1,10 +1,12 @@
-use std::collections::BTreeMap;
+use std::{cell::Cell, collections::BTreeMap, fmt, path::Path, str::FromStr};
 
 use abi_stable::{
     std_types::{RHashMap, ROption, RString, RVec, Tuple2},
     StableAbi,
 };
-use serde::{Deserialize, Serialize, Serializer};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::Log;
 
 #[repr(C)]
 #[derive(StableAbi, Clone, Debug, PartialEq, Serialize, Deserialize)]
@@ -12,9 +14,12 @@ use serde::{Deserialize, Serialize, Serializer};
 pub struct Config {
     #[serde(serialize_with = "ordered_map")]
     entries: RHashMap<RString, EntryType>,
+    // schema version of the plugin's config, bump it in get_config_entries when keys are renamed or change type
+    #[serde(default)]
+    version: u32,
 }
 
-fn ordered_map<S, K: Ord + Serialize, V: Serialize>(value: &RHashMap<K, V>, serializer: S) -> Result<S::Ok, S::Error>
+pub(crate) fn ordered_map<S, K: Ord + Serialize, V: Serialize>(value: &RHashMap<K, V>, serializer: S) -> Result<S::Ok, S::Error>
 where
     S: Serializer,
 {
@@ -22,9 +27,74 @@ where
     ordered.serialize(serializer)
 }
 
+// json has no NaN or infinity and serde_json writes them as null, which then fails to read back as a float
+// so non finite floats are written as "NaN", "inf" and "-inf", null is read back as NaN
+struct JsonFloat(f64);
+
+impl Serialize for JsonFloat {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.0 {
+            value if value.is_finite() => serializer.serialize_f64(value),
+            value if value.is_nan() => serializer.serialize_str("NaN"),
+            value if value > 0.0 => serializer.serialize_str("inf"),
+            _ => serializer.serialize_str("-inf"),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for JsonFloat {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Number(f64),
+            Text(String),
+        }
+        Ok(JsonFloat(match Option::<Repr>::deserialize(deserializer)? {
+            None => f64::NAN,
+            Some(Repr::Number(value)) => value,
+            Some(Repr::Text(text)) => match text.as_str() {
+                "NaN" => f64::NAN,
+                "inf" => f64::INFINITY,
+                "-inf" => f64::NEG_INFINITY,
+                _ => return Err(serde::de::Error::custom(format!("expected a number, found {}", text))),
+            },
+        }))
+    }
+}
+
+pub(crate) fn serialize_float<S: Serializer>(value: &f64, serializer: S) -> Result<S::Ok, S::Error> {
+    JsonFloat(*value).serialize(serializer)
+}
+
+pub(crate) fn deserialize_float<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    JsonFloat::deserialize(deserializer).map(|float| float.0)
+}
+
+fn serialize_float_option<S: Serializer>(value: &ROption<f64>, serializer: S) -> Result<S::Ok, S::Error> {
+    value.into_option().map(JsonFloat).serialize(serializer)
+}
+
+fn deserialize_float_option<'de, D: Deserializer<'de>>(deserializer: D) -> Result<ROption<f64>, D::Error> {
+    Option::<JsonFloat>::deserialize(deserializer).map(|float| float.map(|float| float.0).into())
+}
+
 impl Config {
     pub fn new() -> Self {
-        Self { entries: RHashMap::new() }
+        Self {
+            entries: RHashMap::new(),
+            version: 0,
+        }
+    }
+    pub fn with_version(mut self, version: u32) -> Self {
+        self.version = version;
+        self
+    }
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+    pub fn set_version(&mut self, version: u32) {
+        self.version = version;
     }
     pub fn get_or_default(&self, key: &str, defaults: &Config) -> Option<EntryType> {
         self.entries.get(key).cloned().or_else(|| defaults.entries.get(key).cloned())
@@ -52,6 +122,179 @@ impl Config {
     }
 }
 
+impl Config {
+    // checks this (user) config against the plugin's defaults, the defaults decide the type and the min/max/options of every entry
+    pub fn validate_against(&self, defaults: &Config) -> Vec<ConfigError> {
+        let mut errors = Vec::new();
+        self.check_against(defaults, |error, _| errors.push(error));
+        errors
+    }
+    // returns a config with every problem validate_against would report fixed, logging each fix
+    // wrong types, invalid enum values, NaN floats, empty secrets and missing keys get the default, out of range numbers are clamped and unknown keys are dropped
+    pub fn repair(&self, defaults: &Config, logger: &impl Log) -> Config {
+        self.check_against(defaults, |error, fix| logger.warn(&format!("Repaired config: {}, {}", error, fix)))
+    }
+    fn check_against(&self, defaults: &Config, mut on_error: impl FnMut(ConfigError, String)) -> Config {
+        let mut repaired = Config::new().with_version(defaults.version);
+        let defaults_ordered: BTreeMap<_, _> = defaults.iter().collect();
+        for (key, default) in defaults_ordered {
+            let name = key.to_string();
+            let Some(value) = self.get(key) else {
+                on_error(ConfigError::MissingKey { key: name }, "using the default".into());
+                repaired.insert(key.clone(), default.clone());
+                continue;
+            };
+            if value.variant() != default.variant() {
+                on_error(
+                    ConfigError::WrongType {
+                        key: name,
+                        expected: default.variant_name(),
+                        found: value.variant_name(),
+                    },
+                    "using the default".into(),
+                );
+                repaired.insert(key.clone(), default.clone());
+                continue;
+            }
+            let entry = match (value, default) {
+                (EntryType::Int { value, .. }, EntryType::Int { min, max, .. }) => {
+                    let clamped = clamp(*value, min.into_option(), max.into_option());
+                    if clamped != *value {
+                        on_error(
+                            ConfigError::IntOutOfRange {
+                                key: name,
+                                value: *value,
+                                min: min.into_option(),
+                                max: max.into_option(),
+                            },
+                            format!("clamped to {}", clamped),
+                        );
+                    }
+                    EntryType::Int {
+                        value: clamped,
+                        min: *min,
+                        max: *max,
+                    }
+                }
+                (EntryType::Float { value, .. }, EntryType::Float { value: default_value, min, max }) if value.is_nan() => {
+                    on_error(ConfigError::FloatNotANumber { key: name }, "using the default".into());
+                    EntryType::Float {
+                        value: *default_value,
+                        min: *min,
+                        max: *max,
+                    }
+                }
+                (EntryType::Float { value, .. }, EntryType::Float { min, max, .. }) => {
+                    let clamped = clamp(*value, min.into_option(), max.into_option());
+                    if clamped != *value {
+                        on_error(
+                            ConfigError::FloatOutOfRange {
+                                key: name,
+                                value: *value,
+                                min: min.into_option(),
+                                max: max.into_option(),
+                            },
+                            format!("clamped to {}", clamped),
+                        );
+                    }
+                    EntryType::Float {
+                        value: clamped,
+                        min: *min,
+                        max: *max,
+                    }
+                }
+                (EntryType::Enum { value, .. }, EntryType::Enum { value: default_value, options }) => {
+                    if options.is_empty() || options.iter().any(|o| o.value == *value) {
+                        EntryType::Enum {
+                            value: *value,
+                            options: options.clone(),
+                        }
+                    } else {
+                        on_error(ConfigError::InvalidEnumValue { key: name, value: *value }, "using the default".into());
+                        EntryType::Enum {
+                            value: *default_value,
+                            options: options.clone(),
+                        }
+                    }
+                }
+                // a secret that was masked somewhere along the way comes back empty
+                (EntryType::Secret { value }, EntryType::Secret { value: default_value }) if value.is_empty() && !default_value.is_empty() => {
+                    on_error(ConfigError::EmptySecret { key: name }, "using the default".into());
+                    default.clone()
+                }
+                (EntryType::Path { value, .. }, EntryType::Path { value: default_value, kind }) => {
+                    if kind.matches(Path::new(value.as_str())) {
+                        EntryType::Path {
+                            value: value.clone(),
+                            kind: *kind,
+                        }
+                    } else {
+                        on_error(ConfigError::WrongPathKind { key: name, expected: *kind }, "using the default".into());
+                        EntryType::Path {
+                            value: default_value.clone(),
+                            kind: *kind,
+                        }
+                    }
+                }
+                (value, _) => value.clone(),
+            };
+            repaired.insert(key.clone(), entry);
+        }
+        let unknown: BTreeMap<_, _> = self.iter().filter(|(key, _)| defaults.get(key).is_none()).collect();
+        for key in unknown.keys() {
+            on_error(ConfigError::UnknownKey { key: key.to_string() }, "removed it".into());
+        }
+        repaired
+    }
+}
+
+fn clamp<T: PartialOrd + Copy>(value: T, min: Option<T>, max: Option<T>) -> T {
+    match (min, max) {
+        (Some(min), _) if value < min => min,
+        (_, Some(max)) if value > max => max,
+        _ => value,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigError {
+    WrongType { key: String, expected: &'static str, found: &'static str },
+    IntOutOfRange { key: String, value: i64, min: Option<i64>, max: Option<i64> },
+    FloatOutOfRange { key: String, value: f64, min: Option<f64>, max: Option<f64> },
+    FloatNotANumber { key: String },
+    InvalidEnumValue { key: String, value: u8 },
+    // the path exists but is a file where a directory is expected or the other way around
+    WrongPathKind { key: String, expected: PathKind },
+    UnknownKey { key: String },
+    MissingKey { key: String },
+    // the key is there but the secret is empty, usually because it was masked before it came back
+    EmptySecret { key: String },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::WrongType { key, expected, found } => write!(f, "{} should be {} but is {}", key, expected, found),
+            ConfigError::IntOutOfRange { key, value, min, max } => write!(f, "{} is {} which is outside {}", key, value, range(min, max)),
+            ConfigError::FloatOutOfRange { key, value, min, max } => write!(f, "{} is {} which is outside {}", key, value, range(min, max)),
+            ConfigError::FloatNotANumber { key } => write!(f, "{} is not a number", key),
+            ConfigError::InvalidEnumValue { key, value } => write!(f, "{} is {} which is not one of the options", key, value),
+            ConfigError::WrongPathKind { key, expected } => write!(f, "{} should be a {:?} path", key, expected),
+            ConfigError::UnknownKey { key } => write!(f, "{} is not a known key", key),
+            ConfigError::MissingKey { key } => write!(f, "{} is missing", key),
+            ConfigError::EmptySecret { key } => write!(f, "{} is an empty secret, it was masked or never set", key),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+fn range<T: fmt::Display>(min: &Option<T>, max: &Option<T>) -> String {
+    let min = min.as_ref().map(ToString::to_string).unwrap_or_default();
+    let max = max.as_ref().map(|max| format!("={}", max)).unwrap_or_default();
+    format!("{}..{}", min, max)
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self::new()
@@ -76,10 +319,11 @@ pub enum EntryType {
         max: ROption<i64>,
     },
     Float {
+        #[serde(serialize_with = "serialize_float", deserialize_with = "deserialize_float")]
         value: f64,
-        #[serde(default)]
+        #[serde(default, serialize_with = "serialize_float_option", deserialize_with = "deserialize_float_option")]
         min: ROption<f64>,
-        #[serde(default)]
+        #[serde(default, serialize_with = "serialize_float_option", deserialize_with = "deserialize_float_option")]
         max: ROption<f64>,
     },
     Enum {
@@ -88,6 +332,169 @@ pub enum EntryType {
         options: RVec<EnumEntry>,
     },
     None,
+    List {
+        value: RVec<RString>,
+    },
+    Path {
+        value: RString,
+        #[serde(default)]
+        kind: PathKind,
+    },
+    // packed rgba, same layout as the color of ColoredChar::new_rgba
+    Color {
+        value: u32,
+    },
+    Hotkey {
+        value: KeyChord,
+    },
+    // masked when debug printed or serialized
+    Secret {
+        value: SecretString,
+    },
+}
+
+#[repr(u8)]
+#[derive(StableAbi, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum PathKind {
+    File,
+    Directory,
+    #[default]
+    Any,
+}
+
+impl PathKind {
+    // paths that dont exist yet are accepted
+    pub fn matches(&self, path: &Path) -> bool {
+        match self {
+            PathKind::File => !path.exists() || path.is_file(),
+            PathKind::Directory => !path.exists() || path.is_dir(),
+            PathKind::Any => true,
+        }
+    }
+}
+
+// a key with modifiers, written as e.g. "ctrl+shift+k", the key is always last so the plus key is written as "ctrl++"
+#[repr(C)]
+#[derive(StableAbi, Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub struct KeyChord {
+    pub ctrl: bool,
+    pub alt: bool,
+    pub shift: bool,
+    // super / windows / command key
+    pub meta: bool,
+    pub key: RString,
+}
+
+impl fmt::Display for KeyChord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (held, name) in [(self.ctrl, "ctrl+"), (self.alt, "alt+"), (self.shift, "shift+"), (self.meta, "meta+")] {
+            if held {
+                f.write_str(name)?;
+            }
+        }
+        f.write_str(&self.key)
+    }
+}
+
+impl FromStr for KeyChord {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let trimmed = s.trim();
+        let (modifiers, key) = match trimmed.strip_suffix("++") {
+            Some(modifiers) => (Some(modifiers), "+"),
+            None if trimmed == "+" => (None, "+"),
+            None => match trimmed.rsplit_once('+') {
+                Some((modifiers, key)) => (Some(modifiers), key.trim()),
+                None => (None, trimmed),
+            },
+        };
+        if key.is_empty() {
+            return Err(format!("no key in {}", s));
+        }
+        let mut chord = KeyChord {
+            key: key.into(),
+            ..Default::default()
+        };
+        for part in modifiers.into_iter().flat_map(|modifiers| modifiers.split('+')).map(str::trim) {
+            match part.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => chord.ctrl = true,
+                "alt" | "option" => chord.alt = true,
+                "shift" => chord.shift = true,
+                "meta" | "super" | "win" | "cmd" | "command" => chord.meta = true,
+                "" => return Err(format!("empty modifier in {}", s)),
+                _ => return Err(format!("{} is not a modifier in {}", part, s)),
+            }
+        }
+        Ok(chord)
+    }
+}
+
+// what a SecretString looks like in logs and serialized configs
+pub const SECRET_MASK: &str = "********";
+
+thread_local! {
+    static EXPOSE_SECRETS: Cell<bool> = const { Cell::new(false) };
+}
+
+// serializes secrets in plain text while f runs on this thread, for passing configs to a sandboxed plugin
+// ConfigStore uses it to keep secrets in their own file, hosts that want them somewhere safer like the os keyring should strip them before saving
+pub fn with_exposed_secrets<T>(f: impl FnOnce() -> T) -> T {
+    struct Reset(bool);
+    impl Drop for Reset {
+        fn drop(&mut self) {
+            EXPOSE_SECRETS.with(|expose| expose.set(self.0));
+        }
+    }
+    let _reset = Reset(EXPOSE_SECRETS.with(|expose| expose.replace(true)));
+    f()
+}
+
+pub(crate) fn secrets_exposed() -> bool {
+    EXPOSE_SECRETS.with(Cell::get)
+}
+
+// a masked secret deserializes as an empty one
+#[repr(transparent)]
+#[derive(StableAbi, Clone, PartialEq, Eq, Default)]
+pub struct SecretString(RString);
+
+impl SecretString {
+    pub fn new(secret: &str) -> Self {
+        Self(secret.into())
+    }
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+    pub fn expose_mut(&mut self) -> &mut RString {
+        &mut self.0
+    }
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl fmt::Debug for SecretString {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(SECRET_MASK)
+    }
+}
+
+impl Serialize for SecretString {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if secrets_exposed() {
+            serializer.serialize_str(&self.0)
+        } else {
+            serializer.serialize_str(SECRET_MASK)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for SecretString {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let secret = String::deserialize(deserializer)?;
+        Ok(if secret == SECRET_MASK { Self::default() } else { Self::new(&secret) })
+    }
 }
 
 #[repr(C)]
@@ -168,6 +575,66 @@ impl EntryType {
             _ => None,
         }
     }
+    pub fn as_list(&self) -> Option<&[RString]> {
+        match self {
+            EntryType::List { value } => Some(value),
+            _ => None,
+        }
+    }
+    pub fn as_list_mut(&mut self) -> Option<&mut RVec<RString>> {
+        match self {
+            EntryType::List { value } => Some(value),
+            _ => None,
+        }
+    }
+    pub fn as_path(&self) -> Option<&Path> {
+        match self {
+            EntryType::Path { value, .. } => Some(Path::new(value.as_str())),
+            _ => None,
+        }
+    }
+    pub fn as_path_mut(&mut self) -> Option<&mut RString> {
+        match self {
+            EntryType::Path { value, .. } => Some(value),
+            _ => None,
+        }
+    }
+    pub fn as_color(&self) -> Option<u32> {
+        match self {
+            EntryType::Color { value } => Some(*value),
+            _ => None,
+        }
+    }
+    pub fn as_color_mut(&mut self) -> Option<&mut u32> {
+        match self {
+            EntryType::Color { value } => Some(value),
+            _ => None,
+        }
+    }
+    pub fn as_hotkey(&self) -> Option<&KeyChord> {
+        match self {
+            EntryType::Hotkey { value } => Some(value),
+            _ => None,
+        }
+    }
+    pub fn as_hotkey_mut(&mut self) -> Option<&mut KeyChord> {
+        match self {
+            EntryType::Hotkey { value } => Some(value),
+            _ => None,
+        }
+    }
+    pub fn as_secret(&self) -> Option<&SecretString> {
+        match self {
+            EntryType::Secret { value } => Some(value),
+            _ => None,
+        }
+    }
+    pub fn as_secret_mut(&mut self) -> Option<&mut SecretString> {
+        match self {
+            EntryType::Secret { value } => Some(value),
+            _ => None,
+        }
+    }
     pub fn variant(&self) -> u32 {
         match self {
             EntryType::String { .. } => 0,
@@ -176,6 +643,216 @@ impl EntryType {
             EntryType::Float { .. } => 3,
             EntryType::Enum { .. } => 4,
             EntryType::None => 5,
+            EntryType::List { .. } => 6,
+            EntryType::Path { .. } => 7,
+            EntryType::Color { .. } => 8,
+            EntryType::Hotkey { .. } => 9,
+            EntryType::Secret { .. } => 10,
+        }
+    }
+    pub fn variant_name(&self) -> &'static str {
+        match self {
+            EntryType::String { .. } => "String",
+            EntryType::Bool { .. } => "Bool",
+            EntryType::Int { .. } => "Int",
+            EntryType::Float { .. } => "Float",
+            EntryType::Enum { .. } => "Enum",
+            EntryType::None => "None",
+            EntryType::List { .. } => "List",
+            EntryType::Path { .. } => "Path",
+            EntryType::Color { .. } => "Color",
+            EntryType::Hotkey { .. } => "Hotkey",
+            EntryType::Secret { .. } => "Secret",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{LogLevel, LogLevelOrCustom, Logger};
+
+    fn defaults() -> Config {
+        let mut defaults = Config::new().with_version(2);
+        defaults.insert(
+            "count".into(),
+            EntryType::Int {
+                value: 5,
+                min: ROption::RSome(0),
+                max: ROption::RSome(10),
+            },
+        );
+        defaults.insert(
+            "ratio".into(),
+            EntryType::Float {
+                value: 0.5,
+                min: ROption::RSome(0.0),
+                max: ROption::RSome(1.0),
+            },
+        );
+        defaults.insert("name".into(), EntryType::String { value: "default".into() });
+        defaults.insert(
+            "mode".into(),
+            EntryType::Enum {
+                value: 0,
+                options: vec![EnumEntry::from(("fast", 0)), EnumEntry::from(("slow", 1))].into(),
+            },
+        );
+        defaults
+    }
+
+    fn logger() -> Logger {
+        Logger::new(LogLevelOrCustom::from_min_level(LogLevel::Error), false)
+    }
+
+    #[test]
+    fn valid_config_has_no_errors() {
+        let mut config = defaults();
+        config.insert(
+            "count".into(),
+            EntryType::Int {
+                value: 10,
+                min: ROption::RNone,
+                max: ROption::RNone,
+            },
+        );
+        assert_eq!(config.validate_against(&defaults()), Vec::new());
+        let repaired = config.repair(&defaults(), &logger());
+        assert_eq!(
+            repaired.get("count"),
+            Some(&EntryType::Int {
+                value: 10,
+                min: ROption::RSome(0),
+                max: ROption::RSome(10)
+            })
+        );
+    }
+
+    #[test]
+    fn reports_every_problem_in_key_order() {
+        let mut config = Config::new();
+        config.insert(
+            "count".into(),
+            EntryType::Int {
+                value: 11,
+                min: ROption::RNone,
+                max: ROption::RNone,
+            },
+        );
+        config.insert("ratio".into(), EntryType::Bool { value: true });
+        config.insert("mode".into(), EntryType::Enum { value: 7, options: RVec::new() });
+        config.insert("extra".into(), EntryType::None);
+        assert_eq!(
+            config.validate_against(&defaults()),
+            vec![
+                ConfigError::IntOutOfRange {
+                    key: "count".into(),
+                    value: 11,
+                    min: Some(0),
+                    max: Some(10)
+                },
+                ConfigError::InvalidEnumValue { key: "mode".into(), value: 7 },
+                ConfigError::MissingKey { key: "name".into() },
+                ConfigError::WrongType {
+                    key: "ratio".into(),
+                    expected: "Float",
+                    found: "Bool"
+                },
+                ConfigError::UnknownKey { key: "extra".into() },
+            ]
+        );
+    }
+
+    #[test]
+    fn repair_clamps_and_falls_back_to_defaults() {
+        let mut config = Config::new();
+        config.insert(
+            "count".into(),
+            EntryType::Int {
+                value: -3,
+                min: ROption::RNone,
+                max: ROption::RNone,
+            },
+        );
+        config.insert(
+            "ratio".into(),
+            EntryType::Float {
+                value: 2.0,
+                min: ROption::RNone,
+                max: ROption::RNone,
+            },
+        );
+        config.insert("mode".into(), EntryType::Enum { value: 7, options: RVec::new() });
+        config.insert("extra".into(), EntryType::None);
+        let repaired = config.repair(&defaults(), &logger());
+        let mut expected = defaults();
+        expected.insert(
+            "count".into(),
+            EntryType::Int {
+                value: 0,
+                min: ROption::RSome(0),
+                max: ROption::RSome(10),
+            },
+        );
+        expected.insert(
+            "ratio".into(),
+            EntryType::Float {
+                value: 1.0,
+                min: ROption::RSome(0.0),
+                max: ROption::RSome(1.0),
+            },
+        );
+        assert_eq!(repaired, expected);
+        assert_eq!(repaired.version(), 2);
+        assert_eq!(repaired.validate_against(&defaults()), Vec::new());
+    }
+
+    #[test]
+    fn key_chords_round_trip() {
+        for text in ["k", "ctrl+shift+k", "ctrl+alt+shift+meta+F5", "+", "ctrl++", "shift+alt++"] {
+            let chord: KeyChord = text.parse().unwrap();
+            assert_eq!(chord.to_string().parse::<KeyChord>(), Ok(chord), "{}", text);
         }
+        let plus: KeyChord = "ctrl++".parse().unwrap();
+        assert!(plus.ctrl && !plus.shift);
+        assert_eq!(plus.key, "+");
+        assert_eq!(plus.to_string(), "ctrl++");
+        assert_eq!("Control + Super + k".parse::<KeyChord>().map(|chord| chord.to_string()), Ok("ctrl+meta+k".to_string()));
+        assert!("ctrl+".parse::<KeyChord>().is_err());
+        assert!("ctrl++k".parse::<KeyChord>().is_err());
+        assert!("j+k".parse::<KeyChord>().is_err());
+        assert!("".parse::<KeyChord>().is_err());
+    }
+
+    #[test]
+    fn empty_secrets_use_the_default() {
+        let mut defaults = Config::new();
+        defaults.insert(
+            "token".into(),
+            EntryType::Secret {
+                value: SecretString::new("default"),
+            },
+        );
+        defaults.insert("optional".into(), EntryType::Secret { value: SecretString::default() });
+        let mut config = Config::new();
+        config.insert("token".into(), EntryType::Secret { value: SecretString::default() });
+        config.insert("optional".into(), EntryType::Secret { value: SecretString::default() });
+        assert_eq!(config.validate_against(&defaults), vec![ConfigError::EmptySecret { key: "token".into() }]);
+        assert_eq!(config.repair(&defaults, &logger()), defaults);
+    }
+
+    #[test]
+    fn nan_floats_use_the_default() {
+        let mut config = defaults();
+        config.insert(
+            "ratio".into(),
+            EntryType::Float {
+                value: f64::NAN,
+                min: ROption::RNone,
+                max: ROption::RNone,
+            },
+        );
+        assert_eq!(config.validate_against(&defaults()), vec![ConfigError::FloatNotANumber { key: "ratio".into() }]);
+        assert_eq!(config.repair(&defaults(), &logger()).get("ratio"), defaults().get("ratio"));
     }
 }
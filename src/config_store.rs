@@ -0,0 +1,194 @@
+// stores each plugin's config as its own json file in a config directory, named after the PluginId filename
+// loaded configs are migrated if their version is older than the defaults and repaired against the plugin's defaults before they are applied,
+// a migrated config is saved right away so the migration only runs once,
+// writes go to a temp file that is renamed over the old one
+// secrets are masked in the config file and kept in plain text in a separate "{filename}.secrets.json" only the current user can read
+
+use std::{
+    fs,
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context};
+
+use crate::{migrate_and_repair, with_exposed_secrets, Config, Log, PluginId, PluginLibrary, PluginRegistry};
+
+pub struct ConfigStore {
+    dir: PathBuf,
+}
+
+impl ConfigStore {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+    pub fn path_for(&self, plugin_id: &PluginId) -> PathBuf {
+        self.dir.join(format!("{}.json", plugin_id.filename))
+    }
+    pub fn secrets_path_for(&self, plugin_id: &PluginId) -> PathBuf {
+        self.dir.join(format!("{}.secrets.json", plugin_id.filename))
+    }
+    // None if nothing was saved for the plugin yet, secrets are filled in from the secrets file
+    pub fn read(&self, plugin_id: &PluginId) -> anyhow::Result<Option<Config>> {
+        let Some(mut config) = read_config(&self.path_for(plugin_id))? else {
+            return Ok(None);
+        };
+        if let Some(secrets) = read_config(&self.secrets_path_for(plugin_id))? {
+            for (key, value) in secrets.iter().filter(|(_, value)| value.as_secret().is_some()) {
+                config.insert(key.clone(), value.clone());
+            }
+        }
+        Ok(Some(config))
+    }
+    // the saved config migrated and merged with the plugin's defaults, falls back to the defaults if the file cant be read
+    pub fn load(&self, library: &(impl PluginLibrary + ?Sized), logger: &impl Log) -> Config {
+        match self.read(&library.plugin_id()) {
+            Ok(Some(config)) => {
+                let from_version = config.version();
+                let config = migrate_and_repair(library, config, logger);
+                // a faulted plugin returns the config unmigrated, keep the old file around for the next attempt
+                if config.version() > from_version && !library.is_faulted() {
+                    if let Err(e) = self.save(&library.plugin_id(), &config) {
+                        logger.error(&format!("Failed to save migrated config for {}: {:#}", library.name(), e));
+                    }
+                }
+                config
+            }
+            Ok(None) => library.get_config_entries(),
+            Err(e) => {
+                logger.error(&format!("Using default config for {}: {:#}", library.name(), e));
+                library.get_config_entries()
+            }
+        }
+    }
+    // writes to a temp file next to the real one and renames it over, so a crash never leaves a half written config
+    pub fn save(&self, plugin_id: &PluginId, config: &Config) -> anyhow::Result<()> {
+        fs::create_dir_all(&self.dir).with_context(|| format!("Failed to create {}", self.dir.display()))?;
+        let mut secrets = Config::new().with_version(config.version());
+        for (key, value) in config.iter().filter(|(_, value)| value.as_secret().is_some()) {
+            secrets.insert(key.clone(), value.clone());
+        }
+        if !secrets.empty() {
+            let contents = with_exposed_secrets(|| serde_json::to_string_pretty(&secrets))?;
+            write_atomic(&self.secrets_path_for(plugin_id), &contents, true)?;
+        }
+        write_atomic(&self.path_for(plugin_id), &serde_json::to_string_pretty(config)?, false)
+    }
+    // loads and applies the config of every plugin in the registry
+    pub fn load_all(&self, registry: &mut PluginRegistry, logger: &impl Log) {
+        let configs: Vec<(PluginId, Config)> = registry.plugins().map(|p| (p.plugin_id(), self.load(&**p, logger))).collect();
+        for (plugin_id, config) in configs {
+            if !registry.apply_config(&plugin_id, config) {
+                logger.info(&format!("Config for {} is applied once the plugin is no longer busy", plugin_id.filename));
+            }
+        }
+    }
+    // for when the user changes settings, repairs the config against the defaults, saves it and applies it
+    // a plugin busy on another thread gets the config once it is free, see PluginRegistry::apply_config
+    pub fn update(&self, registry: &mut PluginRegistry, plugin_id: &PluginId, config: Config, logger: &impl Log) -> anyhow::Result<()> {
+        let library = registry.get(plugin_id).ok_or(anyhow!("Plugin {} is not loaded", plugin_id.filename))?;
+        let config = config.repair(&library.get_config_entries(), logger);
+        self.save(plugin_id, &config)?;
+        if !registry.apply_config(plugin_id, config) {
+            logger.info(&format!("Config for {} was saved, it is applied once the plugin is no longer busy", plugin_id.filename));
+        }
+        Ok(())
+    }
+}
+
+fn read_config(path: &Path) -> anyhow::Result<Option<Config>> {
+    match fs::read_to_string(path) {
+        Ok(contents) => Ok(Some(serde_json::from_str(&contents).with_context(|| format!("Failed to parse {}", path.display()))?)),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    }
+}
+
+// private files are only readable by the current user where the platform supports it
+fn write_atomic(path: &Path, contents: &str, private: bool) -> anyhow::Result<()> {
+    let temp = path.with_extension("json.tmp");
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    if private {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    #[cfg(not(unix))]
+    let _ = private;
+    let mut file = options.open(&temp).with_context(|| format!("Failed to create {}", temp.display()))?;
+    file.write_all(contents.as_bytes())?;
+    file.sync_all()?;
+    fs::rename(&temp, path).with_context(|| format!("Failed to replace {}", path.display()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fake::FakePlugin, EntryType, LogLevelOrCustom, Logger, SecretString, SECRET_MASK};
+
+    #[test]
+    fn secrets_survive_save_and_read() {
+        let dir = std::env::temp_dir().join(format!("quick-search-config-store-{}", std::process::id()));
+        let store = ConfigStore::new(dir.clone());
+        let plugin_id = PluginId { filename: "secrets.so".into() };
+        let mut config = Config::new().with_version(1);
+        config.insert(
+            "token".into(),
+            EntryType::Secret {
+                value: SecretString::new("hunter2"),
+            },
+        );
+        config.insert("name".into(), EntryType::String { value: "visible".into() });
+        store.save(&plugin_id, &config).unwrap();
+
+        let saved = fs::read_to_string(store.path_for(&plugin_id)).unwrap();
+        assert!(saved.contains(SECRET_MASK) && !saved.contains("hunter2"));
+        assert!(!fs::read_to_string(store.secrets_path_for(&plugin_id)).unwrap().contains("visible"));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(fs::metadata(store.secrets_path_for(&plugin_id)).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+        assert_eq!(store.read(&plugin_id).unwrap(), Some(config));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    // version 2 renamed "colour" to "color"
+    fn rename_colour(mut config: Config, from_version: u32) -> Config {
+        if from_version < 2 {
+            if let Some(value) = config.get("colour").cloned() {
+                config.remove(&"colour".into());
+                config.insert("color".into(), value);
+            }
+        }
+        config.with_version(2)
+    }
+
+    #[test]
+    fn load_migrates_repairs_and_saves() {
+        let dir = std::env::temp_dir().join(format!("quick-search-config-migrate-{}", std::process::id()));
+        let store = ConfigStore::new(dir.clone());
+        let mut defaults = Config::new().with_version(2);
+        defaults.insert("color".into(), EntryType::String { value: "red".into() });
+        defaults.insert("size".into(), EntryType::Bool { value: true });
+        let plugin = FakePlugin::new("migrate.so", &[]).set_config(defaults, rename_colour);
+
+        let mut old = Config::new().with_version(1);
+        old.insert("colour".into(), EntryType::String { value: "blue".into() });
+        store.save(&plugin.plugin_id(), &old).unwrap();
+
+        let logger = Logger::new(LogLevelOrCustom::from_levels(&[]), false);
+        let loaded = store.load(&plugin, &logger);
+        assert_eq!(loaded.version(), 2);
+        assert_eq!(loaded.get("color").and_then(EntryType::as_string), Some("blue"));
+        assert_eq!(loaded.get("size").and_then(EntryType::as_bool), Some(true));
+        assert!(loaded.get("colour").is_none());
+        assert_eq!(store.read(&plugin.plugin_id()).unwrap(), Some(loaded));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,481 @@
+// human editable text form of a Config, one `key = value` per line with # comments
+// only values are written, the types and min/max/options come from the plugin defaults when the text is read back
+// a non zero schema version is written as an @version line
+//
+// @version = 2
+// # strings are quoted, enums are written as the name of their option
+// name = "hello world"
+// enabled = true
+// count = 3
+// ratio = 0.5
+// mode = fast
+// roots = ["~/Documents", "~/Downloads"]
+// highlight = #ff8800ff
+// open = ctrl+shift+k
+
+use std::collections::BTreeMap;
+
+use abi_stable::std_types::{RString, RVec};
+
+use crate::{config::secrets_exposed, Config, EntryType, EnumEntry, SecretString, SECRET_MASK};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigParseError {
+    // 1 based
+    pub line: usize,
+    pub message: String,
+}
+
+impl std::fmt::Display for ConfigParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ConfigParseError {}
+
+impl Config {
+    // keys are written in sorted order
+    pub fn to_text(&self) -> String {
+        let ordered: BTreeMap<_, _> = self.iter().collect();
+        let mut text = String::new();
+        if self.version() != 0 {
+            text.push_str(&format!("@version = {}\n", self.version()));
+        }
+        for (key, value) in ordered {
+            text.push_str(&format!("{} = {}\n", write_key(key), write_value(value)));
+        }
+        text
+    }
+    // parses text written by to_text (or by hand), the defaults decide the type of every key
+    // keys the defaults dont have, e.g. renamed since the text was written, are kept as strings, as are values of a text older than the defaults
+    // that no longer parse as their type, so migrate_config can carry them over, repair drops or replaces whatever is left
+    // keys missing from the text are missing from the result too, use repair to fill them in and clamp values
+    pub fn from_text(text: &str, defaults: &Config) -> Result<Config, ConfigParseError> {
+        let mut config = Config::new();
+        for (index, line) in text.lines().enumerate() {
+            let error = |message: String| ConfigParseError { line: index + 1, message };
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(version) = line.strip_prefix("@version") {
+                let version = version.trim_start().strip_prefix('=').map(str::trim).unwrap_or_default();
+                config.set_version(version.parse().map_err(|_| error(format!("expected a version number, found {}", version)))?);
+                continue;
+            }
+            let (key, value) = split_key(line).map_err(error)?;
+            let value = value.trim();
+            let value = match defaults.get(&key).map(|default| parse_value(value, default)) {
+                Some(Ok(value)) => value,
+                Some(Err(_)) if config.version() < defaults.version() => untyped(value).map_err(|message| error(format!("{}: {}", key, message)))?,
+                Some(Err(message)) => return Err(error(format!("{}: {}", key, message))),
+                None => untyped(value).map_err(|message| error(format!("{}: {}", key, message)))?,
+            };
+            config.insert(key.into(), value);
+        }
+        Ok(config)
+    }
+}
+
+fn is_bare(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.'))
+}
+
+fn quote(s: &str) -> String {
+    let mut quoted = String::from('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+// parses a quoted string at the start of s, returns the string and the rest of s after the closing quote
+fn unquote(s: &str) -> Result<(String, &str), String> {
+    let mut chars = s.char_indices();
+    if !matches!(chars.next(), Some((_, '"'))) {
+        return Err("expected a quoted string".into());
+    }
+    let mut unquoted = String::new();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Ok((unquoted, &s[i + 1..])),
+            '\\' => match chars.next() {
+                Some((_, '"')) => unquoted.push('"'),
+                Some((_, '\\')) => unquoted.push('\\'),
+                Some((_, 'n')) => unquoted.push('\n'),
+                Some((_, 'r')) => unquoted.push('\r'),
+                Some((_, 't')) => unquoted.push('\t'),
+                Some((_, c)) => return Err(format!("unknown escape \\{}", c)),
+                None => break,
+            },
+            c => unquoted.push(c),
+        }
+    }
+    Err("unterminated string".into())
+}
+
+fn write_key(key: &str) -> String {
+    if is_bare(key) {
+        key.to_owned()
+    } else {
+        quote(key)
+    }
+}
+
+fn split_key(line: &str) -> Result<(String, &str), String> {
+    let (key, rest) = if line.starts_with('"') {
+        unquote(line)?
+    } else {
+        let end = line.find(|c: char| c == '=' || c.is_whitespace()).unwrap_or(line.len());
+        (line[..end].to_owned(), &line[end..])
+    };
+    let value = rest.trim_start().strip_prefix('=').ok_or(format!("expected = after {}", key))?;
+    Ok((key, value))
+}
+
+fn write_value(value: &EntryType) -> String {
+    match value {
+        EntryType::String { value } => quote(value),
+        EntryType::Bool { value } => value.to_string(),
+        EntryType::Int { value, .. } => value.to_string(),
+        // debug formatting always keeps a decimal point and round trips exactly
+        EntryType::Float { value, .. } => format!("{:?}", value),
+        EntryType::Enum { value, options } => match options.iter().find(|o| o.value == *value) {
+            Some(option) if is_bare(&option.name) => option.name.to_string(),
+            Some(option) => quote(&option.name),
+            None => value.to_string(),
+        },
+        EntryType::None => "none".into(),
+        EntryType::List { value } => format!("[{}]", value.iter().map(|item| quote(item)).collect::<Vec<_>>().join(", ")),
+        EntryType::Path { value, .. } => quote(value),
+        EntryType::Color { value } => format!("#{:08x}", value),
+        EntryType::Hotkey { value } => value.to_string(),
+        EntryType::Secret { value } if secrets_exposed() => quote(value.expose()),
+        EntryType::Secret { .. } => quote(SECRET_MASK),
+    }
+}
+
+// a quoted string, or the text as is if it isnt quoted
+fn string_value(s: &str) -> Result<String, String> {
+    if s.starts_with('"') {
+        let (value, rest) = unquote(s)?;
+        if !rest.trim().is_empty() {
+            return Err(format!("unexpected {} after string", rest.trim()));
+        }
+        Ok(value)
+    } else {
+        Ok(s.to_owned())
+    }
+}
+
+// for values whose type isnt known
+fn untyped(s: &str) -> Result<EntryType, String> {
+    Ok(EntryType::String { value: string_value(s)?.into() })
+}
+
+fn parse_value(s: &str, default: &EntryType) -> Result<EntryType, String> {
+    Ok(match default {
+        EntryType::String { .. } => EntryType::String { value: string_value(s)?.into() },
+        EntryType::Bool { .. } => EntryType::Bool {
+            value: s.parse().map_err(|_| format!("expected true or false, found {}", s))?,
+        },
+        EntryType::Int { min, max, .. } => EntryType::Int {
+            value: s.parse().map_err(|_| format!("expected a whole number, found {}", s))?,
+            min: *min,
+            max: *max,
+        },
+        EntryType::Float { min, max, .. } => EntryType::Float {
+            value: s.parse().map_err(|_| format!("expected a number, found {}", s))?,
+            min: *min,
+            max: *max,
+        },
+        EntryType::Enum { options, .. } => EntryType::Enum {
+            value: parse_enum(s, options)?,
+            options: options.clone(),
+        },
+        EntryType::None => EntryType::None,
+        EntryType::List { .. } => EntryType::List { value: parse_list(s)? },
+        EntryType::Path { kind, .. } => EntryType::Path {
+            value: string_value(s)?.into(),
+            kind: *kind,
+        },
+        EntryType::Color { .. } => EntryType::Color { value: parse_color(s)? },
+        EntryType::Hotkey { .. } => EntryType::Hotkey { value: string_value(s)?.parse()? },
+        EntryType::Secret { .. } => EntryType::Secret {
+            value: match string_value(s)? {
+                secret if secret == SECRET_MASK => SecretString::default(),
+                secret => SecretString::new(&secret),
+            },
+        },
+    })
+}
+
+// ["a", "b"], unquoted items are taken as is up to the next comma
+fn parse_list(s: &str) -> Result<RVec<RString>, String> {
+    let mut rest = s
+        .strip_prefix('[')
+        .and_then(|s| s.strip_suffix(']'))
+        .ok_or(format!("expected a [list], found {}", s))?
+        .trim();
+    let mut items = RVec::new();
+    while !rest.is_empty() {
+        let (item, after) = if rest.starts_with('"') {
+            unquote(rest)?
+        } else {
+            let end = rest.find(',').unwrap_or(rest.len());
+            (rest[..end].trim().to_owned(), &rest[end..])
+        };
+        items.push(item.into());
+        let after = after.trim_start();
+        rest = match after.strip_prefix(',') {
+            Some(after) => after.trim_start(),
+            None if after.is_empty() => after,
+            None => return Err(format!("expected , between list items, found {}", after)),
+        };
+    }
+    Ok(items)
+}
+
+// #rrggbbaa, or #rrggbb for an opaque color
+fn parse_color(s: &str) -> Result<u32, String> {
+    let hex = string_value(s)?;
+    let hex = hex.strip_prefix('#').unwrap_or(&hex);
+    let value = u32::from_str_radix(hex, 16).map_err(|_| format!("expected a #rrggbbaa color, found {}", s))?;
+    match hex.len() {
+        8 => Ok(value),
+        6 => Ok(value << 8 | 0xff),
+        _ => Err(format!("expected a #rrggbbaa color, found {}", s)),
+    }
+}
+
+// an option name (ignoring case) or its number
+fn parse_enum(s: &str, options: &RVec<EnumEntry>) -> Result<u8, String> {
+    let name = string_value(s)?;
+    if let Some(option) = options.iter().find(|o| o.name.eq_ignore_ascii_case(&name)) {
+        return Ok(option.value);
+    }
+    name.parse().map_err(|_| {
+        let names: Vec<&str> = options.iter().map(|o| o.name.as_str()).collect();
+        format!("expected one of {}, found {}", names.join(", "), s)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{with_exposed_secrets, KeyChord, LogLevelOrCustom, Logger, PathKind};
+    use abi_stable::std_types::ROption;
+
+    fn every_variant() -> Config {
+        let mut config = Config::new();
+        config.insert("string".into(), EntryType::String { value: "hello world".into() });
+        config.insert("bool".into(), EntryType::Bool { value: true });
+        config.insert(
+            "int".into(),
+            EntryType::Int {
+                value: -3,
+                min: ROption::RSome(-5),
+                max: ROption::RNone,
+            },
+        );
+        config.insert(
+            "float".into(),
+            EntryType::Float {
+                value: 0.1,
+                min: ROption::RNone,
+                max: ROption::RSome(1.0),
+            },
+        );
+        config.insert(
+            "enum".into(),
+            EntryType::Enum {
+                value: 1,
+                options: vec![EnumEntry::from(("fast", 0)), EnumEntry::from(("very slow", 1))].into(),
+            },
+        );
+        config.insert("none".into(), EntryType::None);
+        config.insert(
+            "list".into(),
+            EntryType::List {
+                value: vec![RString::from("a"), RString::from("b")].into(),
+            },
+        );
+        config.insert(
+            "path".into(),
+            EntryType::Path {
+                value: "~/My Documents".into(),
+                kind: PathKind::Directory,
+            },
+        );
+        config.insert("color".into(), EntryType::Color { value: 0xff8800cc });
+        config.insert(
+            "hotkey".into(),
+            EntryType::Hotkey {
+                value: "ctrl+shift+k".parse().unwrap(),
+            },
+        );
+        config.insert(
+            "secret".into(),
+            EntryType::Secret {
+                value: SecretString::new("hunter2"),
+            },
+        );
+        config
+    }
+
+    fn round_trip(config: &Config) -> Config {
+        Config::from_text(&config.to_text(), config).unwrap()
+    }
+
+    #[test]
+    fn round_trips_every_variant() {
+        let config = every_variant();
+        assert_eq!(with_exposed_secrets(|| round_trip(&config)), config);
+    }
+
+    #[test]
+    fn writes_sorted_values_only() {
+        let text = every_variant().to_text();
+        assert_eq!(
+            text,
+            [
+                "bool = true",
+                "color = #ff8800cc",
+                "enum = \"very slow\"",
+                "float = 0.1",
+                "hotkey = ctrl+shift+k",
+                "int = -3",
+                "list = [\"a\", \"b\"]",
+                "none = none",
+                "path = \"~/My Documents\"",
+                "secret = \"********\"",
+                "string = \"hello world\"",
+                "",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn masked_secrets_read_back_empty() {
+        let config = every_variant();
+        assert_eq!(round_trip(&config).get("secret"), Some(&EntryType::Secret { value: SecretString::default() }));
+    }
+
+    #[test]
+    fn quotes_keys_that_arent_bare() {
+        let mut config = Config::new();
+        for key in ["with space", "with=equals", "with \"quotes\"", "#hash", ""] {
+            config.insert(key.into(), EntryType::Bool { value: true });
+        }
+        config.insert("bare_key-1.0".into(), EntryType::Bool { value: false });
+        let text = config.to_text();
+        assert!(text.contains("\"with=equals\" = true"));
+        assert!(text.contains("\"with \\\"quotes\\\"\" = true"));
+        assert!(text.contains("\"\" = true"));
+        assert!(text.contains("\nbare_key-1.0 = false"));
+        assert_eq!(round_trip(&config), config);
+    }
+
+    #[test]
+    fn escapes_round_trip() {
+        let mut config = Config::new();
+        config.insert(
+            "string".into(),
+            EntryType::String {
+                value: "quote \" backslash \\ newline \n return \r tab \t # = , ]".into(),
+            },
+        );
+        let text = config.to_text();
+        assert_eq!(text.lines().count(), 1);
+        assert_eq!(round_trip(&config), config);
+        assert!(Config::from_text("string = \"bad \\q\"", &config).is_err());
+        assert!(Config::from_text("string = \"unterminated", &config).is_err());
+    }
+
+    #[test]
+    fn lists_with_commas_and_quotes_round_trip() {
+        let mut config = Config::new();
+        let items = ["a, b", "\"quoted\"", "", "[brackets]", "back\\slash"];
+        config.insert(
+            "list".into(),
+            EntryType::List {
+                value: items.iter().map(|item| RString::from(*item)).collect(),
+            },
+        );
+        assert_eq!(round_trip(&config), config);
+        config.insert("list".into(), EntryType::List { value: RVec::new() });
+        assert_eq!(config.to_text(), "list = []\n");
+        assert_eq!(round_trip(&config), config);
+        let parsed = Config::from_text("list = [ plain , \"x, y\" ,bare ]", &config).unwrap();
+        assert_eq!(
+            parsed.get("list").and_then(EntryType::as_list),
+            Some(&[RString::from("plain"), RString::from("x, y"), RString::from("bare")][..])
+        );
+    }
+
+    #[test]
+    fn enums_accept_names_and_numbers() {
+        let defaults = every_variant();
+        let value = |text: &str| Config::from_text(text, &defaults).map(|config| config.get("enum").and_then(EntryType::as_enum));
+        assert_eq!(value("enum = fast"), Ok(Some(0)));
+        assert_eq!(value("enum = FAST"), Ok(Some(0)));
+        assert_eq!(value("enum = \"Very Slow\""), Ok(Some(1)));
+        assert_eq!(value("enum = 1"), Ok(Some(1)));
+        assert!(value("enum = medium").is_err());
+        let mut unnamed = Config::new();
+        unnamed.insert("enum".into(), EntryType::Enum { value: 7, options: RVec::new() });
+        assert_eq!(unnamed.to_text(), "enum = 7\n");
+        assert_eq!(round_trip(&unnamed), unnamed);
+    }
+
+    #[test]
+    fn version_line() {
+        let config = Config::new().with_version(3);
+        assert_eq!(config.to_text(), "@version = 3\n");
+        assert_eq!(round_trip(&config), config);
+        assert_eq!(Config::new().to_text(), "");
+        assert_eq!(Config::from_text("  @version=4  ", &Config::new()).map(|config| config.version()), Ok(4));
+        assert_eq!(Config::from_text("\n@version = x", &Config::new()).map_err(|e| e.line), Err(2));
+    }
+
+    #[test]
+    fn comments_hand_written_values_and_errors() {
+        let defaults = every_variant();
+        let text = "# comment\n\n  string = unquoted text  \ncolor = #ff8800\nhotkey = \"ctrl++\"\n";
+        let config = Config::from_text(text, &defaults).unwrap();
+        assert_eq!(config.get("string").and_then(EntryType::as_string), Some("unquoted text"));
+        assert_eq!(config.get("color").and_then(EntryType::as_color), Some(0xff8800ff));
+        assert_eq!(config.get("hotkey").and_then(EntryType::as_hotkey).map(KeyChord::to_string), Some("ctrl++".to_string()));
+        assert_eq!(Config::from_text("bool\n", &defaults).map_err(|e| e.line), Err(1));
+        assert!(Config::from_text("int = 1.5", &defaults).is_err());
+    }
+
+    #[test]
+    fn keeps_keys_for_migration() {
+        let defaults = every_variant().with_version(2);
+        let config = Config::from_text("renamed = \"old name\"\nsize = [1, 2]\n", &defaults).unwrap();
+        assert_eq!(config.get("renamed").and_then(EntryType::as_string), Some("old name"));
+        assert_eq!(config.get("size").and_then(EntryType::as_string), Some("[1, 2]"));
+
+        // an older text whose value no longer parses as the current type
+        let config = Config::from_text("@version = 1\nint = fast\nbool = false\n", &defaults).unwrap();
+        assert_eq!(config.get("int").and_then(EntryType::as_string), Some("fast"));
+        assert_eq!(config.get("bool").and_then(EntryType::as_bool), Some(false));
+        // a current text still has to match
+        assert!(Config::from_text("@version = 2\nint = fast\n", &defaults).is_err());
+
+        let repaired = config.repair(&defaults, &Logger::new(LogLevelOrCustom::from_levels(&[]), false));
+        assert_eq!(repaired.get("int"), defaults.get("int"));
+        assert!(repaired.get("renamed").is_none());
+    }
+}
@@ -0,0 +1,324 @@
+// host side query dispatcher, runs each plugin's search on a worker pool and stops waiting for a plugin once its deadline passes
+// a plugin's deadline starts when a worker picks up its search, time spent waiting in the queue for a free worker doesnt count
+// a plugin that overruns gets its token cancelled and whatever it sent so far is returned, the overrun is logged and counted
+// the worker running an overrun search is replaced with a new one so a plugin that ignores its token cant starve the pool
+// cancelling the token given to dispatch stops every plugin and returns right away, e.g. when a newer query supersedes this one
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU8, Ordering},
+        mpsc, Arc, Mutex, OnceLock,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use crate::{CancellationToken, Log, PluginId, PluginLibrary, Query, ResultSink, ScopedLogger, SearchResult, StreamEvent};
+
+// returns false if the worker running it was replaced in the meantime and should exit
+type Job = Box<dyn FnOnce() -> bool + Send + 'static>;
+
+// how often a dispatch checks whether it was cancelled while waiting on a plugin
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+struct WorkerPool {
+    sender: Option<mpsc::Sender<Job>>,
+    receiver: Arc<Mutex<mpsc::Receiver<Job>>>,
+    workers: Vec<JoinHandle<()>>,
+    // used to name the threads, counts replaced workers too
+    spawned: usize,
+}
+
+impl WorkerPool {
+    fn new(size: usize) -> Self {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let mut pool = Self {
+            sender: Some(sender),
+            receiver: Arc::new(Mutex::new(receiver)),
+            workers: Vec::new(),
+            spawned: 0,
+        };
+        for _ in 0..size.max(1) {
+            pool.add_worker();
+        }
+        pool
+    }
+    fn add_worker(&mut self) {
+        let receiver = Arc::clone(&self.receiver);
+        let worker = thread::Builder::new()
+            .name(format!("quick-search-worker-{}", self.spawned))
+            .spawn(move || loop {
+                let job = match receiver.lock() {
+                    Ok(receiver) => receiver.recv(),
+                    Err(_) => return,
+                };
+                let keep_running = match job {
+                    Ok(job) => job(),
+                    Err(_) => false,
+                };
+                if !keep_running {
+                    return;
+                }
+            })
+            .expect("failed to spawn worker thread");
+        self.spawned += 1;
+        self.workers.retain(|worker| !worker.is_finished());
+        self.workers.push(worker);
+    }
+    fn execute(&self, job: Job) {
+        if let Some(sender) = &self.sender {
+            let _ = sender.send(job);
+        }
+    }
+}
+
+impl Drop for WorkerPool {
+    fn drop(&mut self) {
+        // workers exit once the channel closes, they arent joined since a stuck plugin would block the host forever
+        std::mem::drop(self.sender.take());
+        self.workers.clear();
+    }
+}
+
+const QUEUED: u8 = 0;
+const RUNNING: u8 = 1;
+const FINISHED: u8 = 2;
+// the dispatcher gave up on the job and replaced its worker
+const ABANDONED: u8 = 3;
+
+// shared between a dispatched job and the dispatcher waiting on it
+#[derive(Default)]
+struct JobState {
+    started: OnceLock<Instant>,
+    state: AtomicU8,
+}
+
+impl JobState {
+    fn start(&self) {
+        let _ = self.started.set(Instant::now());
+        let _ = self.state.compare_exchange(QUEUED, RUNNING, Ordering::AcqRel, Ordering::Acquire);
+    }
+    // false if the job was abandoned while it ran
+    fn finish(&self) -> bool {
+        self.state.compare_exchange(RUNNING, FINISHED, Ordering::AcqRel, Ordering::Acquire).is_ok()
+    }
+    // true if the job was still running, in which case its worker has to be replaced
+    fn abandon(&self) -> bool {
+        self.state.compare_exchange(RUNNING, ABANDONED, Ordering::AcqRel, Ordering::Acquire).is_ok()
+    }
+    fn started(&self) -> Option<Instant> {
+        self.started.get().copied()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PluginResults {
+    pub plugin_id: PluginId,
+    pub results: Vec<SearchResult>,
+    // the plugin didnt finish before its deadline, results are whatever it sent before that
+    pub timed_out: bool,
+}
+
+pub struct QueryDispatcher {
+    pool: WorkerPool,
+    default_deadline: Duration,
+    // keyed by PluginId filename
+    deadlines: HashMap<String, Duration>,
+    overruns: HashMap<String, u32>,
+    logger: ScopedLogger,
+}
+
+impl QueryDispatcher {
+    pub fn new(workers: usize, default_deadline: Duration, logger: ScopedLogger) -> Self {
+        Self {
+            pool: WorkerPool::new(workers),
+            default_deadline,
+            deadlines: HashMap::new(),
+            overruns: HashMap::new(),
+            logger,
+        }
+    }
+    pub fn set_default_deadline(&mut self, deadline: Duration) {
+        self.default_deadline = deadline;
+    }
+    pub fn set_deadline(&mut self, plugin_id: &PluginId, deadline: Duration) {
+        self.deadlines.insert(plugin_id.filename.to_string(), deadline);
+    }
+    pub fn deadline(&self, plugin_id: &PluginId) -> Duration {
+        self.deadlines.get(plugin_id.filename.as_ref()).copied().unwrap_or(self.default_deadline)
+    }
+    // searches every plugin at once, returning once each plugin has finished or run out of time
+    // faulted plugins are skipped
+    pub fn dispatch<'a, P: PluginLibrary + ?Sized + 'static>(
+        &mut self,
+        plugins: impl IntoIterator<Item = &'a Arc<P>>,
+        query: &Query,
+        token: &CancellationToken,
+    ) -> Vec<PluginResults> {
+        let running: Vec<_> = plugins
+            .into_iter()
+            .filter(|plugin| !plugin.is_faulted())
+            .map(|plugin| {
+                let (sink, stream) = ResultSink::channel();
+                let token = token.child();
+                let state = Arc::new(JobState::default());
+                let job_plugin = Arc::clone(plugin);
+                let job_token = token.clone();
+                let job_query = query.clone();
+                let job_state = Arc::clone(&state);
+                self.pool.execute(Box::new(move || {
+                    job_state.start();
+                    // a search that was cancelled while queued isnt started at all
+                    if !job_token.is_cancelled() {
+                        job_plugin.search_into(&job_query, &job_token, sink);
+                    }
+                    job_state.finish()
+                }));
+                (plugin.plugin_id(), stream, token, state)
+            })
+            .collect();
+
+        let mut all_results = Vec::with_capacity(running.len());
+        for (plugin_id, stream, token, state) in running {
+            let deadline = self.deadline(&plugin_id);
+            let mut results = Vec::new();
+            let mut timed_out = false;
+            loop {
+                // None while the search is still queued
+                let remaining = state.started().map(|started| deadline.saturating_sub(started.elapsed()));
+                match stream.recv_batch_timeout(remaining.unwrap_or(CANCEL_POLL_INTERVAL).min(CANCEL_POLL_INTERVAL)) {
+                    StreamEvent::Batch(batch) => results.extend(batch),
+                    StreamEvent::Done => break,
+                    StreamEvent::Timeout if token.is_cancelled() => break,
+                    StreamEvent::Timeout if remaining.is_none_or(|remaining| !remaining.is_zero()) => continue,
+                    StreamEvent::Timeout => {
+                        timed_out = true;
+                        token.cancel();
+                        if state.abandon() {
+                            self.pool.add_worker();
+                        }
+                        let count = self.overruns.entry(plugin_id.filename.to_string()).or_default();
+                        *count += 1;
+                        self.logger.warn(&format!(
+                            "Plugin {} exceeded its {:?} search deadline ({} overruns so far)",
+                            plugin_id.filename, deadline, count
+                        ));
+                        break;
+                    }
+                }
+            }
+            all_results.push(PluginResults { plugin_id, results, timed_out });
+        }
+        all_results
+    }
+    pub fn overruns(&self, plugin_id: &PluginId) -> u32 {
+        self.overruns.get(plugin_id.filename.as_ref()).copied().unwrap_or_default()
+    }
+    // plugins that have overrun their deadline, most overruns first
+    pub fn slow_plugins(&self) -> Vec<(String, u32)> {
+        let mut slow: Vec<(String, u32)> = self.overruns.iter().map(|(name, count)| (name.clone(), *count)).collect();
+        slow.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        slow
+    }
+    pub fn reset_overruns(&mut self) {
+        self.overruns.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fake::FakePlugin, LogLevelOrCustom, Logger};
+
+    fn dispatcher(workers: usize) -> QueryDispatcher {
+        let logger = Logger::new(LogLevelOrCustom::from_levels(&[]), false);
+        QueryDispatcher::new(workers, Duration::from_secs(2), logger.new_scoped("dispatch"))
+    }
+
+    fn titles(results: &PluginResults) -> Vec<&str> {
+        results.results.iter().map(SearchResult::title).collect()
+    }
+
+    #[test]
+    fn overrunning_plugin_returns_partial_results() {
+        let fast = Arc::new(FakePlugin::new("fast", &["x", "y"]));
+        let slow = Arc::new(FakePlugin::new("slow", &["a", "b"]).set_stall(1, Duration::from_secs(5)));
+        let mut dispatcher = dispatcher(2);
+        dispatcher.set_deadline(&slow.plugin_id(), Duration::from_millis(100));
+        let started = Instant::now();
+        let results = dispatcher.dispatch([&fast, &slow], &Query::new("q"), &CancellationToken::new());
+        assert!(started.elapsed() < Duration::from_secs(2));
+
+        assert_eq!(titles(&results[0]), ["x", "y"]);
+        assert!(!results[0].timed_out);
+        assert_eq!(titles(&results[1]), ["a"]);
+        assert!(results[1].timed_out);
+        assert_eq!(dispatcher.overruns(&slow.plugin_id()), 1);
+        assert_eq!(dispatcher.overruns(&fast.plugin_id()), 0);
+        assert_eq!(dispatcher.slow_plugins(), vec![("slow".to_string(), 1)]);
+    }
+
+    #[test]
+    fn time_spent_queued_doesnt_count() {
+        // one worker, so the second plugin waits for the first to finish before it starts
+        let first = Arc::new(FakePlugin::new("first", &["a"]).set_stall(0, Duration::from_millis(200)));
+        let second = Arc::new(FakePlugin::new("second", &["b"]));
+        let mut dispatcher = dispatcher(1);
+        dispatcher.set_deadline(&second.plugin_id(), Duration::from_millis(100));
+        let results = dispatcher.dispatch([&first, &second], &Query::new("q"), &CancellationToken::new());
+
+        assert_eq!(titles(&results[1]), ["b"]);
+        assert!(!results[1].timed_out);
+        assert_eq!(dispatcher.overruns(&second.plugin_id()), 0);
+    }
+
+    #[test]
+    fn stuck_worker_is_replaced() {
+        let stuck = Arc::new(FakePlugin::new("stuck", &["a"]).set_stall(0, Duration::from_secs(3)).ignore_token());
+        let mut dispatcher = dispatcher(1);
+        dispatcher.set_deadline(&stuck.plugin_id(), Duration::from_millis(50));
+        let results = dispatcher.dispatch([&stuck], &Query::new("q"), &CancellationToken::new());
+        assert!(results[0].timed_out);
+
+        // the only worker is still stuck, a replacement has to pick this up
+        let fast = Arc::new(FakePlugin::new("fast", &["x"]));
+        let started = Instant::now();
+        let results = dispatcher.dispatch([&fast], &Query::new("q"), &CancellationToken::new());
+        assert_eq!(titles(&results[0]), ["x"]);
+        assert!(!results[0].timed_out);
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn cancelling_the_token_stops_every_plugin() {
+        let slow = Arc::new(FakePlugin::new("slow", &["a", "b"]).set_stall(1, Duration::from_secs(5)));
+        let mut dispatcher = dispatcher(1);
+        let token = CancellationToken::new();
+        let canceller = token.clone();
+        let cancelling = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            canceller.cancel();
+        });
+        let started = Instant::now();
+        let results = dispatcher.dispatch([&slow], &Query::new("q"), &token);
+        cancelling.join().unwrap();
+
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert!(!results[0].timed_out);
+        assert_eq!(dispatcher.overruns(&slow.plugin_id()), 0);
+    }
+
+    #[test]
+    fn cancelled_searches_arent_started() {
+        let plugin = Arc::new(FakePlugin::new("plugin", &["a"]));
+        let mut dispatcher = dispatcher(1);
+        let token = CancellationToken::new();
+        token.cancel();
+        let results = dispatcher.dispatch([&plugin], &Query::new("q"), &token);
+        assert!(results[0].results.is_empty());
+        // the worker may pick the job up after dispatch returned
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(plugin.searches(), 0);
+    }
+}
@@ -0,0 +1,230 @@
+// host side search engine, sends a query to every plugin at once and merges the results into one ordered list
+// queries starting with a plugin keyword only go to that plugin, plugins that allow it can be answered from an optional result cache
+// with a frecency store set, results the user picked are boosted, the store is handed to the plugins so their execute records the pick
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::Duration,
+};
+
+use crate::{
+    route_query, CancellationToken, ExecuteOutcome, PluginId, PluginLibrary, Query, QueryDispatcher, RankedResult, Ranker, ResultCache, Route, ScopedLogger, SearchResult,
+    SharedFrecency, PRIMARY_ACTION,
+};
+
+pub struct SearchEngine {
+    dispatcher: QueryDispatcher,
+    ranker: Ranker,
+    cache: Option<ResultCache>,
+    frecency: Option<SharedFrecency>,
+}
+
+impl SearchEngine {
+    pub fn new(workers: usize, deadline: Duration, logger: ScopedLogger) -> Self {
+        Self::with_parts(QueryDispatcher::new(workers, deadline, logger), Ranker::new())
+    }
+    pub fn with_parts(dispatcher: QueryDispatcher, ranker: Ranker) -> Self {
+        Self {
+            dispatcher,
+            ranker,
+            cache: None,
+            frecency: None,
+        }
+    }
+    pub fn dispatcher(&self) -> &QueryDispatcher {
+        &self.dispatcher
+    }
+    pub fn dispatcher_mut(&mut self) -> &mut QueryDispatcher {
+        &mut self.dispatcher
+    }
+    pub fn ranker(&self) -> &Ranker {
+        &self.ranker
+    }
+    pub fn ranker_mut(&mut self) -> &mut Ranker {
+        &mut self.ranker
+    }
+    pub fn enable_cache(&mut self, capacity: usize) {
+        self.cache = Some(ResultCache::new(capacity));
+    }
+    pub fn disable_cache(&mut self) {
+        self.cache = None;
+    }
+    pub fn cache_mut(&mut self) -> Option<&mut ResultCache> {
+        self.cache.as_mut()
+    }
+    // use the same store for PluginRegistry::set_frecency so picks made outside the engine are recorded too
+    pub fn set_frecency(&mut self, frecency: Option<SharedFrecency>) {
+        self.frecency = frecency;
+    }
+    pub fn frecency(&self) -> Option<&SharedFrecency> {
+        self.frecency.as_ref()
+    }
+    // hands the frecency store to the plugin, which records the pick, and executes the result
+    pub fn execute(&mut self, plugin: &(impl PluginLibrary + ?Sized), selected_result: &SearchResult) -> ExecuteOutcome {
+        self.execute_action(plugin, selected_result, PRIMARY_ACTION)
+    }
+    pub fn execute_action(&mut self, plugin: &(impl PluginLibrary + ?Sized), selected_result: &SearchResult, action_id: &str) -> ExecuteOutcome {
+        if self.frecency.is_some() {
+            plugin.set_frecency(self.frecency.clone());
+        }
+        if action_id == PRIMARY_ACTION {
+            plugin.execute(selected_result)
+        } else {
+            plugin.execute_action(selected_result, action_id)
+        }
+    }
+    // searches all plugins concurrently and returns the ranked results, results with the same title are only kept once
+    pub fn search<'a, P: PluginLibrary + ?Sized + 'static>(&mut self, plugins: impl IntoIterator<Item = &'a Arc<P>> + Clone, query: &str) -> Vec<RankedResult> {
+        self.search_query(plugins, Query::new(query))
+    }
+    // like search but keeps the generation, locale and max results of the query, routing replaces its text and keyword
+    // the merged list is cut off at max_results
+    pub fn search_query<'a, P: PluginLibrary + ?Sized + 'static>(&mut self, plugins: impl IntoIterator<Item = &'a Arc<P>> + Clone, query: Query) -> Vec<RankedResult> {
+        self.search_cancellable(plugins, query, &CancellationToken::new())
+    }
+    // like search_query but stops waiting on plugins once the token is cancelled, returning whatever was found so far
+    pub fn search_cancellable<'a, P: PluginLibrary + ?Sized + 'static>(
+        &mut self,
+        plugins: impl IntoIterator<Item = &'a Arc<P>> + Clone,
+        query: Query,
+        token: &CancellationToken,
+    ) -> Vec<RankedResult> {
+        let max_results = query.max_results();
+        let (targets, query): (Vec<&Arc<P>>, Query) = match route_query(plugins.clone(), query.text()) {
+            Route::Targeted { plugin_id, keyword, query: text } => (
+                plugins.into_iter().filter(|p| p.plugin_id() == plugin_id).collect(),
+                query.set_text(&text).set_keyword(Some(&keyword)),
+            ),
+            Route::Global { .. } => (plugins.into_iter().collect(), query),
+        };
+        let batches = self.search_targets(&targets, &query, token);
+        let mut ranked = dedup_titles(match &self.frecency {
+            Some(frecency) => {
+                let frecency = frecency.lock().unwrap_or_else(|e| e.into_inner());
+                self.ranker.rank_with_boost(batches, |plugin_id, result| frecency.boost(plugin_id, result))
+            }
+            None => self.ranker.rank(batches),
+        });
+        if let Some(max_results) = max_results {
+            ranked.truncate(max_results as usize);
+        }
+        ranked
+    }
+    fn search_targets<P: PluginLibrary + ?Sized + 'static>(&mut self, targets: &[&Arc<P>], query: &Query, token: &CancellationToken) -> Vec<(PluginId, Vec<SearchResult>)> {
+        let mut found: HashMap<String, Vec<SearchResult>> = HashMap::new();
+        let mut misses = Vec::new();
+        for plugin in targets {
+            match self.cache.as_mut().and_then(|cache| cache.get(&plugin.plugin_id(), query, &plugin.cache_policy())) {
+                Some(results) => {
+                    found.insert(plugin.plugin_id().filename.to_string(), results);
+                }
+                None => misses.push(*plugin),
+            }
+        }
+        for result in self.dispatcher.dispatch(misses.iter().copied(), query, token) {
+            // partial results from a plugin that ran out of time or was cancelled shouldnt be served again later
+            if let (Some(cache), false) = (self.cache.as_mut(), result.timed_out || token.is_cancelled()) {
+                if let Some(plugin) = misses.iter().find(|p| p.plugin_id() == result.plugin_id) {
+                    cache.insert(&result.plugin_id, query, &plugin.cache_policy(), result.results.clone());
+                }
+            }
+            found.insert(result.plugin_id.filename.to_string(), result.results);
+        }
+        // keep the order plugins were given in, the ranker interleaves equal scores in that order
+        targets
+            .iter()
+            .filter_map(|plugin| {
+                let plugin_id = plugin.plugin_id();
+                found.remove(plugin_id.filename.as_ref()).map(|results| (plugin_id, results))
+            })
+            .collect()
+    }
+}
+
+// keeps the best ranked result for each title, results are already ordered so that is the first one seen
+fn dedup_titles(ranked: Vec<RankedResult>) -> Vec<RankedResult> {
+    let mut seen = HashSet::new();
+    ranked.into_iter().filter(|r| seen.insert(r.result.title().to_owned())).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fake::FakePlugin, CachePolicy, LogLevelOrCustom, Logger};
+
+    fn engine() -> SearchEngine {
+        let logger = Logger::new(LogLevelOrCustom::from_levels(&[]), false);
+        SearchEngine::new(4, Duration::from_secs(2), logger.new_scoped("engine"))
+    }
+
+    fn scored(results: &[(&str, f64)]) -> Vec<SearchResult> {
+        results.iter().map(|(title, score)| SearchResult::new(title).set_score(*score)).collect()
+    }
+
+    fn titles(ranked: &[RankedResult]) -> Vec<&str> {
+        ranked.iter().map(|r| r.result.title()).collect()
+    }
+
+    #[test]
+    fn merges_by_weighted_score_then_plugin_order() {
+        let a = Arc::new(FakePlugin::new("a", &[]).set_results(scored(&[("a1", 2.0), ("a2", 1.0)])));
+        let b = Arc::new(FakePlugin::new("b", &[]).set_results(scored(&[("b1", 10.0), ("b2", 5.0)])));
+        let mut engine = engine();
+        assert_eq!(titles(&engine.search([&a, &b], "q")), ["a1", "b1", "a2", "b2"]);
+
+        engine.ranker_mut().set_weight(&b.plugin_id(), 0.5);
+        assert_eq!(titles(&engine.search([&a, &b], "q")), ["a1", "b1", "a2", "b2"]);
+        engine.ranker_mut().set_weight(&a.plugin_id(), 0.25);
+        assert_eq!(titles(&engine.search([&a, &b], "q")), ["b1", "a1", "a2", "b2"]);
+    }
+
+    #[test]
+    fn keeps_the_best_ranked_result_for_each_title() {
+        let a = Arc::new(FakePlugin::new("a", &["same", "only a"]));
+        let b = Arc::new(FakePlugin::new("b", &["same"]));
+        let mut engine = engine();
+        engine.ranker_mut().set_weight(&b.plugin_id(), 2.0);
+        let ranked = engine.search([&a, &b], "q");
+        assert_eq!(titles(&ranked), ["same", "only a"]);
+        assert_eq!(ranked[0].plugin_id, b.plugin_id());
+    }
+
+    #[test]
+    fn truncates_to_max_results() {
+        let a = Arc::new(FakePlugin::new("a", &["a1", "a2", "a3"]));
+        let b = Arc::new(FakePlugin::new("b", &["b1", "b2"]));
+        let mut engine = engine();
+        assert_eq!(engine.search_query([&a, &b], Query::new("q").set_max_results(Some(3))).len(), 3);
+        assert_eq!(engine.search_query([&a, &b], Query::new("q").set_max_results(None)).len(), 5);
+    }
+
+    #[test]
+    fn caches_only_complete_results() {
+        let policy = CachePolicy::cacheable(Duration::ZERO);
+        let fast = Arc::new(FakePlugin::new("fast", &["x"]).set_cache_policy(policy));
+        let slow = Arc::new(FakePlugin::new("slow", &["a", "b"]).set_stall(1, Duration::from_secs(5)).set_cache_policy(policy));
+        let mut engine = engine();
+        engine.enable_cache(16);
+        engine.dispatcher_mut().set_deadline(&slow.plugin_id(), Duration::from_millis(50));
+
+        assert_eq!(titles(&engine.search([&fast, &slow], "q")), ["x", "a"]);
+        assert_eq!(titles(&engine.search([&fast, &slow], "q")), ["x", "a"]);
+        assert_eq!(fast.searches(), 1);
+        // timed out both times, so it was asked again instead of being served the partial results
+        assert_eq!(slow.searches(), 2);
+    }
+
+    #[test]
+    fn doesnt_cache_cancelled_searches() {
+        let plugin = Arc::new(FakePlugin::new("plugin", &["x"]).set_cache_policy(CachePolicy::cacheable(Duration::ZERO)));
+        let mut engine = engine();
+        engine.enable_cache(16);
+        let token = CancellationToken::new();
+        token.cancel();
+        assert!(engine.search_cancellable([&plugin], Query::new("q"), &token).is_empty());
+        assert_eq!(titles(&engine.search([&plugin], "q")), ["x"]);
+        assert_eq!(titles(&engine.search([&plugin], "q")), ["x"]);
+        assert_eq!(plugin.searches(), 1);
+    }
+}
@@ -0,0 +1,212 @@
+// remembers which results the user picks so the ranking can push frequently and recently chosen ones up
+// every pick adds 1 to a result's score and scores halve every half life, the store is saved as json
+// the store is shared with the plugins through PluginLibrary::set_frecency so every execute records the pick
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::{PluginId, SearchResult};
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct FrecencyEntry {
+    score: f64,
+    // unix time in milliseconds
+    last_used: u64,
+}
+
+// handle given to the engine and to every plugin, picks are recorded through it while searches read the boost
+pub type SharedFrecency = Arc<Mutex<FrecencyStore>>;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FrecencyStore {
+    half_life_secs: u64,
+    // how much a result that has been picked a lot can be boosted, ranked scores are otherwise within 0.0..=plugin weight
+    boost_weight: f64,
+    entries: BTreeMap<String, FrecencyEntry>,
+}
+
+impl Default for FrecencyStore {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(60 * 60 * 24 * 7))
+    }
+}
+
+fn now_millis() -> u64 {
+    std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or_default().as_millis() as u64
+}
+
+// a result is identified by the plugin it came from, its title and its context
+fn key(plugin_id: &PluginId, result: &SearchResult) -> String {
+    format!("{}\u{1f}{}\u{1f}{}", plugin_id.filename, result.title(), result.context())
+}
+
+impl FrecencyStore {
+    pub fn new(half_life: Duration) -> Self {
+        Self {
+            half_life_secs: half_life.as_secs().max(1),
+            boost_weight: 0.5,
+            entries: BTreeMap::new(),
+        }
+    }
+    pub fn set_boost_weight(&mut self, boost_weight: f64) {
+        self.boost_weight = boost_weight;
+    }
+    // a missing file gives an empty store
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(contents) => Ok(serde_json::from_str(&contents)?),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+    // writes to a temp file next to the real one and renames it over, same as ConfigStore::save
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(".tmp");
+        let temp = PathBuf::from(temp);
+        let mut file = fs::File::create(&temp).with_context(|| format!("Failed to create {}", temp.display()))?;
+        file.write_all(serde_json::to_string(self)?.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&temp, path).with_context(|| format!("Failed to replace {}", path.display()))?;
+        Ok(())
+    }
+    pub fn into_shared(self) -> SharedFrecency {
+        Arc::new(Mutex::new(self))
+    }
+    fn decayed(&self, entry: &FrecencyEntry, now: u64) -> f64 {
+        let elapsed_secs = now.saturating_sub(entry.last_used) as f64 / 1000.0;
+        entry.score * 0.5f64.powf(elapsed_secs / self.half_life_secs as f64)
+    }
+    pub fn record(&mut self, plugin_id: &PluginId, result: &SearchResult) {
+        let now = now_millis();
+        let score = self.entries.get(&key(plugin_id, result)).map(|entry| self.decayed(entry, now)).unwrap_or_default();
+        self.entries.insert(
+            key(plugin_id, result),
+            FrecencyEntry {
+                score: score + 1.0,
+                last_used: now,
+            },
+        );
+    }
+    // decayed number of picks
+    pub fn score(&self, plugin_id: &PluginId, result: &SearchResult) -> f64 {
+        self.entries.get(&key(plugin_id, result)).map(|entry| self.decayed(entry, now_millis())).unwrap_or_default()
+    }
+    // score squashed into 0.0..boost_weight, to be added to the ranked score
+    pub fn boost(&self, plugin_id: &PluginId, result: &SearchResult) -> f64 {
+        let score = self.score(plugin_id, result);
+        self.boost_weight * score / (score + 1.0)
+    }
+    // forgets results whose score has decayed below min_score, keeps the file from growing forever
+    pub fn prune(&mut self, min_score: f64) {
+        let now = now_millis();
+        let decayed: Vec<String> = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| self.decayed(entry, now) < min_score)
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in decayed {
+            self.entries.remove(&key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id() -> PluginId {
+        PluginId { filename: "files".into() }
+    }
+
+    // a store with one pick of "old" made a given number of half lives ago
+    fn store_with_old_pick(half_lives: u64) -> FrecencyStore {
+        let mut store = FrecencyStore::new(Duration::from_secs(60));
+        store.entries.insert(
+            key(&id(), &SearchResult::new("old")),
+            FrecencyEntry {
+                score: 1.0,
+                last_used: now_millis() - half_lives * 60 * 1000,
+            },
+        );
+        store
+    }
+
+    #[test]
+    fn scores_halve_every_half_life() {
+        let old = SearchResult::new("old");
+        assert!((store_with_old_pick(1).score(&id(), &old) - 0.5).abs() < 0.01);
+        assert!((store_with_old_pick(2).score(&id(), &old) - 0.25).abs() < 0.01);
+
+        // a new pick adds to the decayed score
+        let mut store = store_with_old_pick(1);
+        store.record(&id(), &old);
+        assert!((store.score(&id(), &old) - 1.5).abs() < 0.01);
+
+        let new = SearchResult::new("new");
+        assert_eq!(store.score(&id(), &new), 0.0);
+        store.record(&id(), &new);
+        store.record(&id(), &new);
+        assert!((store.score(&id(), &new) - 2.0).abs() < 0.01);
+        // same title from another plugin is a different result
+        assert_eq!(store.score(&PluginId { filename: "apps".into() }, &new), 0.0);
+    }
+
+    #[test]
+    fn boost_stays_below_the_weight() {
+        let mut store = FrecencyStore::default();
+        store.set_boost_weight(0.8);
+        let result = SearchResult::new("picked");
+        assert_eq!(store.boost(&id(), &result), 0.0);
+        let mut last = 0.0;
+        for _ in 0..50 {
+            store.record(&id(), &result);
+            let boost = store.boost(&id(), &result);
+            assert!(boost > last && boost < 0.8);
+            last = boost;
+        }
+    }
+
+    #[test]
+    fn prune_forgets_decayed_results() {
+        let mut store = store_with_old_pick(10);
+        let recent = SearchResult::new("recent");
+        store.record(&id(), &recent);
+        store.prune(0.01);
+        assert_eq!(store.entries.len(), 1);
+        assert!(store.score(&id(), &recent) > 0.9);
+    }
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let dir = std::env::temp_dir().join(format!("quick-search-frecency-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("frecency.json");
+
+        let missing = FrecencyStore::load(&path).unwrap();
+        assert!(missing.entries.is_empty());
+        assert_eq!(missing.half_life_secs, FrecencyStore::default().half_life_secs);
+
+        let mut store = FrecencyStore::new(Duration::from_secs(3600));
+        store.set_boost_weight(0.25);
+        store.record(&id(), &SearchResult::new("saved"));
+        store.save(&path).unwrap();
+        assert!(!dir.join("frecency.json.tmp").exists());
+
+        let loaded = FrecencyStore::load(&path).unwrap();
+        assert_eq!(loaded.half_life_secs, 3600);
+        assert_eq!(loaded.boost_weight, 0.25);
+        assert!((loaded.score(&id(), &SearchResult::new("saved")) - 1.0).abs() < 0.01);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,196 @@
+// fuzzy matching for plugins to use instead of rolling their own
+// smith-waterman style local alignment where every query character has to match in order,
+// consecutive matches and matches on word boundaries score higher and gaps between matches are penalized
+
+use abi_stable::StableAbi;
+use serde::{Deserialize, Serialize};
+
+use crate::SearchResult;
+
+const MATCH: i64 = 16;
+const CONSECUTIVE_BONUS: i64 = 8;
+const BOUNDARY_BONUS: i64 = 10;
+const FIRST_CHAR_BONUS: i64 = 8;
+const GAP_START: i64 = 3;
+const GAP_EXTEND: i64 = 1;
+
+// range of characters (not bytes) in a title, end is exclusive
+#[repr(C)]
+#[derive(StableAbi, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HighlightRange {
+    pub start: u32,
+    pub end: u32,
+}
+
+impl HighlightRange {
+    pub fn new(start: u32, end: u32) -> Self {
+        Self { start, end }
+    }
+    pub fn contains(&self, index: u32) -> bool {
+        self.start <= index && index < self.end
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FuzzyMatch {
+    pub score: i64,
+    pub ranges: Vec<HighlightRange>,
+}
+
+impl FuzzyMatch {
+    // sets the score and highlights of the result from this match
+    pub fn apply(self, result: SearchResult) -> SearchResult {
+        result.set_score(self.score as f64).set_highlights(self.ranges)
+    }
+}
+
+// matches the query against the candidate ignoring case and whitespace in the query, None if the query isnt a subsequence of the candidate
+pub fn fuzzy_match(query: &str, candidate: &str) -> Option<FuzzyMatch> {
+    let query: Vec<char> = query.chars().filter(|c| !c.is_whitespace()).map(lowercase).collect();
+    if query.is_empty() {
+        return Some(FuzzyMatch { score: 0, ranges: Vec::new() });
+    }
+    let original: Vec<char> = candidate.chars().collect();
+    let candidate: Vec<char> = original.iter().copied().map(lowercase).collect();
+    let (n, m) = (query.len(), candidate.len());
+    if n > m {
+        return None;
+    }
+    let bonus: Vec<i64> = (0..m).map(|j| boundary_bonus(&original, j)).collect();
+
+    // scores[i][j] is the best score with query[i] matched at candidate[j], previous[i][j] is where query[i - 1] was matched
+    let mut scores = vec![vec![None; m]; n];
+    let mut previous = vec![vec![0; m]; n];
+    for j in 0..m {
+        if candidate[j] == query[0] {
+            scores[0][j] = Some(MATCH + bonus[j] + if j == 0 { FIRST_CHAR_BONUS } else { 0 });
+        }
+    }
+    for i in 1..n {
+        // best score of query[i - 1] matched at least two characters back, with the gap penalty up to the current position applied
+        let mut gapped: Option<(i64, usize)> = None;
+        for j in i..m {
+            if j >= 2 {
+                let extended = gapped.map(|(score, k)| (score - GAP_EXTEND, k));
+                let started = scores[i - 1][j - 2].map(|score| (score - GAP_START, j - 2));
+                gapped = match (extended, started) {
+                    (Some(a), Some(b)) => Some(if b.0 >= a.0 { b } else { a }),
+                    (a, b) => a.or(b),
+                };
+            }
+            if candidate[j] != query[i] {
+                continue;
+            }
+            let consecutive = scores[i - 1][j - 1].map(|score| (score + CONSECUTIVE_BONUS, j - 1));
+            let best = match (consecutive, gapped) {
+                (Some(a), Some(b)) => Some(if a.0 >= b.0 { a } else { b }),
+                (a, b) => a.or(b),
+            };
+            if let Some((score, k)) = best {
+                scores[i][j] = Some(score + MATCH + bonus[j]);
+                previous[i][j] = k;
+            }
+        }
+    }
+
+    let (score, mut j) = (0..m)
+        .filter_map(|j| scores[n - 1][j].map(|score| (score, j)))
+        .max_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)))?;
+    let mut positions = vec![0; n];
+    for i in (0..n).rev() {
+        positions[i] = j;
+        j = previous[i][j];
+    }
+    Some(FuzzyMatch {
+        score,
+        ranges: merge_positions(&positions),
+    })
+}
+
+fn lowercase(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+fn boundary_bonus(chars: &[char], j: usize) -> i64 {
+    let Some(prev) = j.checked_sub(1).map(|p| chars[p]) else {
+        return BOUNDARY_BONUS;
+    };
+    let current = chars[j];
+    if !prev.is_alphanumeric() && current.is_alphanumeric() || prev.is_lowercase() && current.is_uppercase() || !prev.is_numeric() && current.is_numeric() {
+        BOUNDARY_BONUS
+    } else {
+        0
+    }
+}
+
+fn merge_positions(positions: &[usize]) -> Vec<HighlightRange> {
+    let mut ranges: Vec<HighlightRange> = Vec::new();
+    for &position in positions {
+        let position = position as u32;
+        match ranges.last_mut() {
+            Some(range) if range.end == position => range.end += 1,
+            _ => ranges.push(HighlightRange::new(position, position + 1)),
+        }
+    }
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ranges(query: &str, candidate: &str) -> Vec<(u32, u32)> {
+        fuzzy_match(query, candidate).unwrap().ranges.iter().map(|r| (r.start, r.end)).collect()
+    }
+
+    #[test]
+    fn matches_subsequences_only() {
+        assert!(fuzzy_match("zz", "abc").is_none());
+        assert!(fuzzy_match("abcd", "abc").is_none());
+        assert!(fuzzy_match("cba", "abc").is_none());
+        assert!(fuzzy_match("ac", "abc").is_some());
+    }
+
+    #[test]
+    fn empty_query_matches_everything() {
+        assert_eq!(fuzzy_match("", "anything"), Some(FuzzyMatch { score: 0, ranges: Vec::new() }));
+        assert_eq!(fuzzy_match("  ", ""), Some(FuzzyMatch { score: 0, ranges: Vec::new() }));
+    }
+
+    #[test]
+    fn ignores_case_and_query_whitespace() {
+        assert_eq!(ranges("R P", "report.pdf"), ranges("rp", "report.pdf"));
+        assert!(fuzzy_match("ABC", "abc").is_some());
+    }
+
+    #[test]
+    fn highlights_merge_consecutive_characters() {
+        assert_eq!(ranges("rpt", "report.pdf"), vec![(0, 1), (2, 3), (5, 6)]);
+        assert_eq!(ranges("port", "report"), vec![(2, 6)]);
+    }
+
+    #[test]
+    fn prefers_word_boundaries() {
+        assert_eq!(ranges("fb", "foo bar"), vec![(0, 1), (4, 5)]);
+        assert_eq!(ranges("fb", "fooBar"), vec![(0, 1), (3, 4)]);
+    }
+
+    #[test]
+    fn consecutive_and_prefix_matches_score_higher() {
+        let score = |query, candidate| fuzzy_match(query, candidate).unwrap().score;
+        assert!(score("abc", "abcxyz") > score("abc", "axbxcx"));
+        assert!(score("abc", "abc") > score("abc", "xabc"));
+    }
+
+    #[test]
+    fn counts_characters_not_bytes() {
+        assert_eq!(ranges("é", "café"), vec![(3, 4)]);
+    }
+
+    #[test]
+    fn apply_sets_score_and_highlights() {
+        let result = fuzzy_match("ab", "ab").unwrap().apply(SearchResult::new("ab"));
+        assert_eq!(result.highlights(), &[HighlightRange::new(0, 2)]);
+        assert!(result.score() > 0.0);
+    }
+}
@@ -0,0 +1,56 @@
+// splits result lists into sections for display, either by the group a plugin put its results in or by plugin
+
+use std::sync::Arc;
+
+use crate::{ColoredChar, PluginLibrary, RankedResult, SearchResult};
+
+#[derive(Debug)]
+pub struct Section<T> {
+    // group name or PluginId filename, None for results without a group
+    pub id: Option<String>,
+    // empty for results without a group
+    pub header: Vec<ColoredChar>,
+    pub results: Vec<T>,
+}
+
+// sections in the order their first result appears, results keep their order within a section
+fn group_by<T>(items: Vec<T>, key: impl Fn(&T) -> Option<String>) -> Vec<(Option<String>, Vec<T>)> {
+    let mut groups: Vec<(Option<String>, Vec<T>)> = Vec::new();
+    for item in items {
+        let id = key(&item);
+        match groups.iter_mut().find(|(group, _)| *group == id) {
+            Some((_, group)) => group.push(item),
+            None => groups.push((id, vec![item])),
+        }
+    }
+    groups
+}
+
+// groups by SearchResult::group, e.g. "Recent", "Documents", "Folders" from a file search
+pub fn group_results(results: Vec<SearchResult>, header_color: u32) -> Vec<Section<SearchResult>> {
+    group_by(results, |r| r.group().map(str::to_owned))
+        .into_iter()
+        .map(|(id, results)| Section {
+            header: id.as_deref().map(|id| ColoredChar::from_string(id, header_color).into()).unwrap_or_default(),
+            id,
+            results,
+        })
+        .collect()
+}
+
+// groups merged results by the plugin they came from, using the plugin's colored name as the header
+pub fn group_by_plugin<'a, P: PluginLibrary + ?Sized + 'a>(ranked: Vec<RankedResult>, plugins: impl IntoIterator<Item = &'a Arc<P>> + Clone) -> Vec<Section<RankedResult>> {
+    group_by(ranked, |r| Some(r.plugin_id.filename.to_string()))
+        .into_iter()
+        .map(|(id, results)| Section {
+            header: plugins
+                .clone()
+                .into_iter()
+                .find(|p| Some(p.plugin_id().filename.as_ref()) == id.as_deref())
+                .map(|p| p.colored_name())
+                .unwrap_or_default(),
+            id,
+            results,
+        })
+        .collect()
+}
@@ -1,19 +1,62 @@
-#![allow(dead_code, non_camel_case_types, clippy::empty_docs)]
+#![allow(dead_code, non_camel_case_types, non_local_definitions, clippy::empty_docs)]
 
+mod actions;
+mod cache;
 mod chars;
 mod config;
+mod config_store;
+mod config_text;
+mod dispatch;
+mod engine;
+mod frecency;
+mod fuzzy;
+mod grouping;
+mod library;
 mod logging;
+mod panic;
+mod preview;
+mod query;
+mod ranking;
+mod registry;
+mod router;
+#[cfg(feature = "sandbox")]
+mod sandbox;
+mod scheduler;
+mod search;
 
-use std::path::{Path, PathBuf};
+use std::{
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
 
+pub use actions::*;
+pub use cache::*;
 pub use chars::*;
 pub use config::*;
+pub use config_store::*;
+pub use config_text::*;
+pub use dispatch::*;
+pub use engine::*;
+pub use frecency::*;
+pub use fuzzy::*;
+pub use grouping::*;
+pub use library::*;
 pub use logging::*;
+pub use panic::*;
+pub use preview::*;
+pub use query::*;
+pub use ranking::*;
+pub use registry::*;
+pub use router::*;
+#[cfg(feature = "sandbox")]
+pub use sandbox::*;
+pub use scheduler::*;
+pub use search::*;
 
 use abi_stable::{
     library::{LibraryError, RootModule},
     package_version_strings, sabi_trait,
-    std_types::{RBox, RCowStr, RStr, RString, RVec},
+    std_types::{RBox, RCowStr, RHashMap, ROption, RStr, RString, RVec, Tuple2},
     StableAbi,
 };
 
@@ -22,9 +65,39 @@ use serde::{Deserialize, Serialize};
 #[sabi_trait]
 pub trait Searchable: Send + Sync {
     fn search(&self, query: RString) -> RVec<SearchResult>;
+    // cancellable, streaming version of search, results should be sent to the sink in batches as they are found and the token should be checked periodically
+    // the default just wraps search so plugins that dont care about cancellation or streaming keep working
+    fn search_cancellable(&self, query: RString, token: CancellationToken, sink: ResultSink) {
+        if token.is_cancelled() {
+            return;
+        }
+        let results = self.search(query);
+        if !token.is_cancelled() {
+            sink.send_batch(results);
+        }
+    }
+    // same as search_cancellable but with the structured query, plugins that dont opt in get the query text through search_cancellable
+    fn search_query(&self, query: Query, token: CancellationToken, sink: ResultSink) {
+        self.search_cancellable(query.text().into(), token, sink);
+    }
     fn name(&self) -> RStr<'static>;
     fn colored_name(&self) -> RVec<ColoredChar>;
     fn execute(&self, selected_result: &SearchResult);
+    // same as execute but lets the plugin tell the host what to do afterwards, the default runs execute and closes
+    fn execute_with_outcome(&self, selected_result: &SearchResult) -> ExecuteOutcome {
+        self.execute(selected_result);
+        ExecuteOutcome::Close
+    }
+    // called with one of the ids from SearchResult::actions, or PRIMARY_ACTION for the default action
+    fn execute_action(&self, selected_result: &SearchResult, action_id: RString) -> ExecuteOutcome {
+        if action_id == PRIMARY_ACTION {
+            self.execute_with_outcome(selected_result)
+        } else {
+            ExecuteOutcome::ShowError {
+                message: format!("Unknown action: {}", action_id).into(),
+            }
+        }
+    }
     fn plugin_id(&self) -> PluginId;
 
     // config related
@@ -36,7 +109,21 @@ pub trait Searchable: Send + Sync {
     fn get_config_entries(&self) -> Config {
         Config::default()
     }
+    // called before lazy_load_config when the user's saved config has an older version than get_config_entries, should return the config upgraded to the current version
+    // the host repairs the result against the defaults afterwards so keys the migration doesnt touch are still checked
+    fn migrate_config(&self, config: Config, from_version: u32) -> Config {
+        let _ = from_version;
+        config
+    }
     fn version(&self) -> RStr<'static>;
+    // words that target this plugin when typed at the start of a query followed by a space, e.g. "calc 2+2"
+    fn keywords(&self) -> RVec<RString> {
+        RVec::new()
+    }
+    // whether the host may cache results of this plugin, not cacheable by default
+    fn cache_policy(&self) -> CachePolicy {
+        CachePolicy::default()
+    }
 }
 
 #[repr(C)]
@@ -46,6 +133,26 @@ pub struct SearchResult {
     title: RString,
     context: RString,
     extra_info: RString,
+    // higher is better, only compared against other results from the same plugin
+    #[serde(default, serialize_with = "config::serialize_float", deserialize_with = "config::deserialize_float")]
+    score: f64,
+    // characters of the title that matched the query, for the host to highlight
+    #[serde(default)]
+    highlights: RVec<HighlightRange>,
+    // extra actions besides the primary one
+    #[serde(default)]
+    actions: RVec<ResultAction>,
+    #[serde(default)]
+    icon: ROption<ResultIcon>,
+    // shown in the preview pane while the result is selected
+    #[serde(default)]
+    preview: ROption<Preview>,
+    // structured data for the plugin itself, passed back into execute untouched
+    #[serde(default, serialize_with = "config::ordered_map")]
+    metadata: RHashMap<RString, EntryType>,
+    // section within the plugin's results, e.g. "Recent" or "Folders"
+    #[serde(default)]
+    group: ROption<RString>,
 }
 
 type SearchableBox = Searchable_TO<'static, RBox<()>>;
@@ -56,6 +163,13 @@ impl SearchResult {
             title: title.into(),
             context: "".into(),
             extra_info: "".into(),
+            score: 0.0,
+            highlights: RVec::new(),
+            actions: RVec::new(),
+            icon: ROption::RNone,
+            preview: ROption::RNone,
+            metadata: RHashMap::new(),
+            group: ROption::RNone,
         }
     }
     pub fn set_title(mut self, title: &str) -> Self {
@@ -70,6 +184,34 @@ impl SearchResult {
         self.extra_info = extra_info.into();
         self
     }
+    pub fn set_score(mut self, score: f64) -> Self {
+        self.score = score;
+        self
+    }
+    pub fn set_highlights(mut self, highlights: impl Into<RVec<HighlightRange>>) -> Self {
+        self.highlights = highlights.into();
+        self
+    }
+    pub fn add_action(mut self, id: &str, name: &str) -> Self {
+        self.actions.push(ResultAction::new(id, name));
+        self
+    }
+    pub fn set_icon(mut self, icon: ResultIcon) -> Self {
+        self.icon = ROption::RSome(icon);
+        self
+    }
+    pub fn set_preview(mut self, preview: Preview) -> Self {
+        self.preview = ROption::RSome(preview);
+        self
+    }
+    pub fn set_metadata(mut self, key: &str, value: EntryType) -> Self {
+        self.metadata.insert(key.into(), value);
+        self
+    }
+    pub fn set_group(mut self, group: &str) -> Self {
+        self.group = ROption::RSome(group.into());
+        self
+    }
     pub fn title(&self) -> &str {
         &self.title
     }
@@ -79,6 +221,30 @@ impl SearchResult {
     pub fn extra_info(&self) -> &str {
         &self.extra_info
     }
+    pub fn score(&self) -> f64 {
+        self.score
+    }
+    pub fn highlights(&self) -> &[HighlightRange] {
+        &self.highlights
+    }
+    pub fn actions(&self) -> &[ResultAction] {
+        &self.actions
+    }
+    pub fn icon(&self) -> Option<&ResultIcon> {
+        self.icon.as_ref().into()
+    }
+    pub fn preview(&self) -> Option<&Preview> {
+        self.preview.as_ref().into()
+    }
+    pub fn metadata(&self, key: &str) -> Option<&EntryType> {
+        self.metadata.get(key)
+    }
+    pub fn group(&self) -> Option<&str> {
+        self.group.as_ref().map(RString::as_str).into()
+    }
+    pub fn metadata_iter(&self) -> impl Iterator<Item = (&RString, &EntryType)> {
+        self.metadata.iter().map(|Tuple2(key, value)| (key, value))
+    }
 }
 
 #[repr(C)]
@@ -86,8 +252,9 @@ impl SearchResult {
 #[sabi(kind(Prefix(prefix_ref = SearchLib_Ref)))]
 #[sabi(missing_field(panic))]
 pub struct SearchLib {
+    // the plugin should report panics through the FaultFlag, see GuardedSearchable
     #[sabi(last_prefix_field)]
-    pub get_searchable: extern "C" fn(PluginId, ScopedLogger) -> SearchableBox,
+    pub get_searchable: extern "C" fn(PluginId, ScopedLogger, FaultFlag) -> SearchableBox,
 }
 
 #[repr(C)]
@@ -110,6 +277,11 @@ impl RootModule for SearchLib_Ref {
 // 3. raw_lib
 pub struct SearchableLibrary {
     path: PathBuf,
+    logger: ScopedLogger,
+    // set by the plugin once a call into it panics, after that calls are skipped
+    fault: FaultFlag,
+    // picks are recorded here on every execute
+    frecency: Mutex<Option<SharedFrecency>>,
     searchable: Option<SearchableBox>,
     #[cfg(not(feature = "leaky-loader"))]
     raw_lib: Option<abi_stable::library::RawLibrary>,
@@ -117,11 +289,17 @@ pub struct SearchableLibrary {
 
 impl SearchableLibrary {
     pub fn new(path: PathBuf, logger: ScopedLogger) -> Result<Self, LibraryError> {
+        let file = path.clone();
+        Self::new_from(path, &file, logger)
+    }
+    // loads file, e.g. a copy of the plugin, while the PluginId and path() still come from path
+    pub(crate) fn new_from(path: PathBuf, file: &Path, logger: ScopedLogger) -> Result<Self, LibraryError> {
+        let fault = FaultFlag::new();
         #[cfg(not(feature = "leaky-loader"))]
-        let raw_lib = abi_stable::library::RawLibrary::load_at(&path)?;
+        let raw_lib = abi_stable::library::RawLibrary::load_at(file)?;
         #[cfg(feature = "leaky-loader")]
         {
-            check_library(&path)?;
+            check_library(file)?;
         }
         Ok(Self {
             searchable: Some({
@@ -131,7 +309,7 @@ impl SearchableLibrary {
                 }
                 #[cfg(feature = "leaky-loader")]
                 {
-                    load_library(&path)?
+                    load_library(file)?
                 }
             }
             .get_searchable()(
@@ -148,8 +326,12 @@ impl SearchableLibrary {
                             .into()
                     },
                 },
-                logger,
+                logger.clone(),
+                fault.clone(),
             )),
+            logger,
+            fault,
+            frecency: Mutex::new(None),
             #[cfg(not(feature = "leaky-loader"))]
             raw_lib: Some(raw_lib),
             path,
@@ -159,29 +341,93 @@ impl SearchableLibrary {
     fn load(raw_lib: &abi_stable::library::RawLibrary) -> Result<SearchLib_Ref, LibraryError> {
         unsafe { abi_stable::library::lib_header_from_raw_library(raw_lib) }.and_then(|x| x.init_root_module::<SearchLib_Ref>())
     }
-    pub fn search(&self, query: &str) -> Vec<SearchResult> {
-        unsafe { self.searchable.as_ref().unwrap_unchecked() }.search(query.into()).into()
+    // a panic in the plugin aborts the process unless the plugin catches it itself, so this only sees the faults the plugin reports
+    fn call<T>(&self, method: &str, f: impl FnOnce(&SearchableBox) -> T) -> Result<T, PluginError> {
+        self.check_fault(method)?;
+        let searchable = unsafe { self.searchable.as_ref().unwrap_unchecked() };
+        let result = f(searchable);
+        self.fault.get().map_or(Ok(result), Err)
+    }
+    fn call_mut<T>(&mut self, method: &str, f: impl FnOnce(&mut SearchableBox) -> T) -> Result<T, PluginError> {
+        self.check_fault(method)?;
+        let searchable = unsafe { self.searchable.as_mut().unwrap_unchecked() };
+        let result = f(searchable);
+        self.fault.get().map_or(Ok(result), Err)
+    }
+    fn check_fault(&self, method: &str) -> Result<(), PluginError> {
+        if self.is_faulted() {
+            Err(PluginError::Disabled { method: method.into() })
+        } else {
+            Ok(())
+        }
+    }
+}
+
+// all the methods fall back to empty values once the plugin has faulted
+impl PluginLibrary for SearchableLibrary {
+    fn search(&self, query: &str) -> Vec<SearchResult> {
+        self.call("search", |s| s.search(query.into()).into()).unwrap_or_default()
+    }
+    fn search_into(&self, query: &Query, token: &CancellationToken, sink: ResultSink) {
+        let _ = self.call("search_query", |s| s.search_query(query.clone(), token.clone(), sink));
+    }
+    fn name(&self) -> &str {
+        self.call("name", |s| s.name().into()).unwrap_or_default()
+    }
+    fn colored_name(&self) -> Vec<ColoredChar> {
+        self.call("colored_name", |s| s.colored_name().into()).unwrap_or_default()
+    }
+    fn execute(&self, selected_result: &SearchResult) -> ExecuteOutcome {
+        record_pick(self, selected_result);
+        self.call("execute", |s| s.execute_with_outcome(selected_result))
+            .unwrap_or_else(|e| ExecuteOutcome::ShowError { message: e.to_string().into() })
+    }
+    fn execute_action(&self, selected_result: &SearchResult, action_id: &str) -> ExecuteOutcome {
+        record_pick(self, selected_result);
+        self.call("execute_action", |s| s.execute_action(selected_result, action_id.into()))
+            .unwrap_or_else(|e| ExecuteOutcome::ShowError { message: e.to_string().into() })
+    }
+    fn plugin_id(&self) -> PluginId {
+        self.call("plugin_id", |s| s.plugin_id()).unwrap_or_else(|_| PluginId {
+            filename: self.path.file_name().unwrap_or_default().to_string_lossy().into_owned().into(),
+        })
+    }
+    fn lazy_load_config(&mut self, config: Config) {
+        let _ = self.call_mut("lazy_load_config", |s| s.lazy_load_config(config));
+    }
+    fn get_config_entries(&self) -> Config {
+        self.call("get_config_entries", |s| s.get_config_entries()).unwrap_or_default()
+    }
+    fn migrate_config(&self, config: Config, from_version: u32) -> Config {
+        let original = config.clone();
+        self.call("migrate_config", |s| s.migrate_config(config, from_version)).unwrap_or(original)
+    }
+    fn version(&self) -> &str {
+        self.call("version", |s| s.version().into()).unwrap_or_default()
+    }
+    fn keywords(&self) -> Vec<String> {
+        self.call("keywords", |s| s.keywords().into_iter().map(String::from).collect()).unwrap_or_default()
     }
-    pub fn name(&self) -> &str {
-        unsafe { self.searchable.as_ref().unwrap_unchecked() }.name().into()
+    fn cache_policy(&self) -> CachePolicy {
+        self.call("cache_policy", |s| s.cache_policy()).unwrap_or_default()
     }
-    pub fn colored_name(&self) -> Vec<ColoredChar> {
-        unsafe { self.searchable.as_ref().unwrap_unchecked() }.colored_name().into()
+    fn path(&self) -> &Path {
+        &self.path
     }
-    pub fn execute(&self, selected_result: &SearchResult) {
-        unsafe { self.searchable.as_ref().unwrap_unchecked() }.execute(selected_result);
+    fn fault(&self) -> Option<PluginError> {
+        self.fault.get()
     }
-    pub fn plugin_id(&self) -> PluginId {
-        unsafe { self.searchable.as_ref().unwrap_unchecked() }.plugin_id()
+    fn is_faulted(&self) -> bool {
+        self.fault.is_set()
     }
-    pub fn lazy_load_config(&mut self, config: Config) {
-        unsafe { self.searchable.as_mut().unwrap_unchecked() }.lazy_load_config(config);
+    fn clear_fault(&self) {
+        self.fault.clear();
     }
-    pub fn get_config_entries(&self) -> Config {
-        unsafe { self.searchable.as_ref().unwrap_unchecked() }.get_config_entries()
+    fn set_frecency(&self, frecency: Option<SharedFrecency>) {
+        *self.frecency.lock().unwrap_or_else(|e| e.into_inner()) = frecency;
     }
-    pub fn version(&self) -> &'static str {
-        unsafe { self.searchable.as_ref().unwrap_unchecked() }.version().into()
+    fn frecency(&self) -> Option<SharedFrecency> {
+        self.frecency.lock().unwrap_or_else(|e| e.into_inner()).clone()
     }
 }
 
@@ -200,6 +446,7 @@ fn load_library(path: &Path) -> Result<SearchLib_Ref, LibraryError> {
     // SearchLib_Ref::load_from_file(path)
 }
 
+#[cfg(feature = "leaky-loader")]
 fn check_library(path: &Path) -> Result<(), LibraryError> {
     let raw_library = abi_stable::library::RawLibrary::load_at(path)?;
     unsafe { abi_stable::library::lib_header_from_raw_library(&raw_library) }.and_then(|x| x.check_layout::<SearchLib_Ref>())?;
@@ -0,0 +1,233 @@
+// host side interface shared by every kind of loaded plugin, so the registry, dispatcher, engine and config store
+// work the same whether the plugin was loaded into the host (SearchableLibrary) or runs in a child process (SandboxedLibrary)
+// none of the methods fail, once a plugin faults they fall back to empty values, check fault() to tell the difference
+
+use std::{path::Path, thread::Scope};
+
+use crate::{CachePolicy, CancellationToken, ColoredChar, Config, ExecuteOutcome, Log, PluginError, PluginId, Query, ResultSink, ResultStream, SearchResult, SharedFrecency};
+
+pub trait PluginLibrary: Send + Sync {
+    fn search(&self, query: &str) -> Vec<SearchResult>;
+    // blocks until the plugin is done, results are sent to the sink as the plugin finds them
+    fn search_into(&self, query: &Query, token: &CancellationToken, sink: ResultSink);
+    // returns whatever the plugin found before the token was cancelled
+    fn search_cancellable(&self, query: &Query, token: &CancellationToken) -> Vec<SearchResult> {
+        let (sink, stream) = ResultSink::channel();
+        self.search_into(query, token, sink);
+        stream.collect_all()
+    }
+    // runs the search on a thread in the given scope and returns the stream of results as they come in
+    fn search_stream<'scope, 'env>(&'env self, scope: &'scope Scope<'scope, 'env>, query: &Query, token: &CancellationToken) -> ResultStream {
+        let (sink, stream) = ResultSink::channel();
+        let query = query.clone();
+        let token = token.clone();
+        scope.spawn(move || self.search_into(&query, &token, sink));
+        stream
+    }
+    fn name(&self) -> &str;
+    fn colored_name(&self) -> Vec<ColoredChar>;
+    // records the pick in the frecency store, if one is set, and runs the primary action
+    fn execute(&self, selected_result: &SearchResult) -> ExecuteOutcome;
+    fn execute_action(&self, selected_result: &SearchResult, action_id: &str) -> ExecuteOutcome;
+    fn plugin_id(&self) -> PluginId;
+    fn lazy_load_config(&mut self, config: Config);
+    fn get_config_entries(&self) -> Config;
+    // falls back to the config it was given if the plugin faults
+    fn migrate_config(&self, config: Config, from_version: u32) -> Config;
+    fn version(&self) -> &str;
+    fn keywords(&self) -> Vec<String>;
+    fn cache_policy(&self) -> CachePolicy;
+    fn path(&self) -> &Path;
+
+    // the error that disabled the plugin, if any
+    fn fault(&self) -> Option<PluginError>;
+    fn is_faulted(&self) -> bool {
+        self.fault().is_some()
+    }
+    // lets calls through to the plugin again
+    fn clear_fault(&self);
+
+    // the store execute and execute_action record picks in, shared with the SearchEngine that boosts them
+    fn set_frecency(&self, frecency: Option<SharedFrecency>);
+    fn frecency(&self) -> Option<SharedFrecency>;
+}
+
+// migrates a saved config that is older than the plugin's defaults and repairs it against them, used by ConfigStore::load and PluginRegistry::reload
+// a faulted plugin hands the config back unmigrated, it is still repaired
+pub fn migrate_and_repair(library: &(impl PluginLibrary + ?Sized), config: Config, logger: &impl Log) -> Config {
+    let defaults = library.get_config_entries();
+    let config = if config.version() < defaults.version() {
+        logger.info(&format!(
+            "Migrating config for {} from version {} to {}",
+            library.name(),
+            config.version(),
+            defaults.version()
+        ));
+        let from_version = config.version();
+        library.migrate_config(config, from_version)
+    } else {
+        config
+    };
+    config.repair(&defaults, logger)
+}
+
+// for the execute implementations
+pub(crate) fn record_pick(library: &(impl PluginLibrary + ?Sized), selected_result: &SearchResult) {
+    if library.is_faulted() {
+        return;
+    }
+    if let Some(frecency) = library.frecency() {
+        frecency.lock().unwrap_or_else(|e| e.into_inner()).record(&library.plugin_id(), selected_result);
+    }
+}
+
+// stand in for a loaded plugin in the dispatcher, engine and router tests
+#[cfg(test)]
+pub(crate) mod fake {
+    use std::{
+        path::PathBuf,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Mutex,
+        },
+        time::{Duration, Instant},
+    };
+
+    use super::*;
+
+    // sends its first results right away, then waits before sending the rest
+    pub(crate) struct FakePlugin {
+        path: PathBuf,
+        results: Vec<SearchResult>,
+        stall_after: usize,
+        delay: Duration,
+        honours_token: bool,
+        keywords: Vec<String>,
+        cache_policy: CachePolicy,
+        defaults: Config,
+        migrate: fn(Config, u32) -> Config,
+        searches: AtomicUsize,
+        frecency: Mutex<Option<SharedFrecency>>,
+    }
+
+    impl FakePlugin {
+        pub(crate) fn new(name: &str, titles: &[&str]) -> Self {
+            Self {
+                path: PathBuf::from(name),
+                results: titles.iter().map(|title| SearchResult::new(title)).collect(),
+                stall_after: 0,
+                delay: Duration::ZERO,
+                honours_token: true,
+                keywords: Vec::new(),
+                cache_policy: CachePolicy::default(),
+                defaults: Config::new(),
+                migrate: |config, _| config,
+                searches: AtomicUsize::new(0),
+                frecency: Mutex::new(None),
+            }
+        }
+        pub(crate) fn set_results(mut self, results: Vec<SearchResult>) -> Self {
+            self.results = results;
+            self
+        }
+        pub(crate) fn set_stall(mut self, stall_after: usize, delay: Duration) -> Self {
+            self.stall_after = stall_after;
+            self.delay = delay;
+            self
+        }
+        pub(crate) fn ignore_token(mut self) -> Self {
+            self.honours_token = false;
+            self
+        }
+        pub(crate) fn set_keywords(mut self, keywords: &[&str]) -> Self {
+            self.keywords = keywords.iter().map(|keyword| keyword.to_string()).collect();
+            self
+        }
+        pub(crate) fn set_cache_policy(mut self, cache_policy: CachePolicy) -> Self {
+            self.cache_policy = cache_policy;
+            self
+        }
+        pub(crate) fn set_config(mut self, defaults: Config, migrate: fn(Config, u32) -> Config) -> Self {
+            self.defaults = defaults;
+            self.migrate = migrate;
+            self
+        }
+        pub(crate) fn searches(&self) -> usize {
+            self.searches.load(Ordering::SeqCst)
+        }
+        // false if the token was cancelled while waiting
+        fn wait(&self, token: &CancellationToken) -> bool {
+            let started = Instant::now();
+            while started.elapsed() < self.delay {
+                if self.honours_token && token.is_cancelled() {
+                    return false;
+                }
+                std::thread::sleep(Duration::from_millis(1));
+            }
+            true
+        }
+    }
+
+    impl PluginLibrary for FakePlugin {
+        fn search(&self, _query: &str) -> Vec<SearchResult> {
+            self.results.clone()
+        }
+        fn search_into(&self, _query: &Query, token: &CancellationToken, sink: ResultSink) {
+            self.searches.fetch_add(1, Ordering::SeqCst);
+            for (index, result) in self.results.iter().enumerate() {
+                if index == self.stall_after && !self.wait(token) {
+                    return;
+                }
+                sink.push(result.clone());
+            }
+        }
+        fn name(&self) -> &str {
+            self.path.to_str().unwrap_or_default()
+        }
+        fn colored_name(&self) -> Vec<ColoredChar> {
+            Vec::new()
+        }
+        fn execute(&self, selected_result: &SearchResult) -> ExecuteOutcome {
+            record_pick(self, selected_result);
+            ExecuteOutcome::default()
+        }
+        fn execute_action(&self, selected_result: &SearchResult, _action_id: &str) -> ExecuteOutcome {
+            record_pick(self, selected_result);
+            ExecuteOutcome::default()
+        }
+        fn plugin_id(&self) -> PluginId {
+            PluginId {
+                filename: self.name().to_owned().into(),
+            }
+        }
+        fn lazy_load_config(&mut self, _config: Config) {}
+        fn get_config_entries(&self) -> Config {
+            self.defaults.clone()
+        }
+        fn migrate_config(&self, config: Config, from_version: u32) -> Config {
+            (self.migrate)(config, from_version)
+        }
+        fn version(&self) -> &str {
+            "0.0.0"
+        }
+        fn keywords(&self) -> Vec<String> {
+            self.keywords.clone()
+        }
+        fn cache_policy(&self) -> CachePolicy {
+            self.cache_policy
+        }
+        fn path(&self) -> &Path {
+            &self.path
+        }
+        fn fault(&self) -> Option<PluginError> {
+            None
+        }
+        fn clear_fault(&self) {}
+        fn set_frecency(&self, frecency: Option<SharedFrecency>) {
+            *self.frecency.lock().unwrap_or_else(|e| e.into_inner()) = frecency;
+        }
+        fn frecency(&self) -> Option<SharedFrecency> {
+            self.frecency.lock().unwrap_or_else(|e| e.into_inner()).clone()
+        }
+    }
+}
@@ -115,7 +115,7 @@ impl Logger {
 }
 
 #[repr(C)]
-#[derive(StableAbi)]
+#[derive(StableAbi, Clone)]
 pub struct ScopedLogger {
     log_level: RArc<RMutex<LogLevelOrCustom>>,
     source: RArc<RString>,
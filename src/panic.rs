@@ -0,0 +1,254 @@
+// panic containment for plugin calls
+// a panic can't unwind across the abi boundary (abi_stable aborts before the host could catch it), so it has to be caught inside the plugin
+// plugins return GuardedSearchable from get_searchable, or wrap their trait methods with guard_plugin_call!, and either one reports the panic
+// through the FaultFlag the host handed to get_searchable, the host checks the flag after every call and stops calling a plugin once it is set
+
+use std::{
+    any::Any,
+    panic::{catch_unwind, AssertUnwindSafe},
+};
+
+use abi_stable::{
+    external_types::RMutex,
+    std_types::{RArc, ROption, RStr, RString, RVec},
+    StableAbi,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{CachePolicy, CancellationToken, ColoredChar, Config, ExecuteOutcome, Log, PluginId, Query, ResultSink, ScopedLogger, SearchResult, Searchable};
+
+#[repr(C)]
+#[derive(StableAbi, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PluginError {
+    Panicked { method: RString, message: RString },
+    // the plugin faulted earlier so the call was skipped
+    Disabled { method: RString },
+    // the call didnt reach the plugin or its answer got lost, e.g. a sandboxed plugin crashed, the plugin isnt disabled by it
+    Unavailable { method: RString, message: RString },
+}
+
+impl std::fmt::Display for PluginError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            PluginError::Panicked { method, message } => write!(f, "plugin panicked in {}: {}", method, message),
+            PluginError::Disabled { method } => write!(f, "plugin is disabled after a fault, skipped {}", method),
+            PluginError::Unavailable { method, message } => write!(f, "plugin could not be called for {}: {}", method, message),
+        }
+    }
+}
+
+impl std::error::Error for PluginError {}
+
+// shared between the host and the plugin, the plugin sets it when it catches a panic and the host stops calling the plugin
+#[repr(C)]
+#[derive(StableAbi, Clone, Default)]
+pub struct FaultFlag {
+    fault: RArc<RMutex<ROption<PluginError>>>,
+}
+
+impl FaultFlag {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn set(&self, error: PluginError) {
+        *self.fault.lock() = ROption::RSome(error);
+    }
+    pub fn get(&self) -> Option<PluginError> {
+        self.fault.lock().clone().into()
+    }
+    pub fn is_set(&self) -> bool {
+        self.fault.lock().is_some()
+    }
+    pub fn clear(&self) {
+        *self.fault.lock() = ROption::RNone;
+    }
+}
+
+// runs f, turning a panic into a PluginError that is also logged through the logger
+pub fn catch_plugin_panic<T>(logger: &impl Log, method: &str, f: impl FnOnce() -> T) -> Result<T, PluginError> {
+    catch_unwind(AssertUnwindSafe(f)).map_err(|payload| {
+        let error = PluginError::Panicked {
+            method: method.into(),
+            message: panic_message(payload.as_ref()).into(),
+        };
+        logger.error(&error.to_string());
+        error
+    })
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic payload".to_string()
+    }
+}
+
+// for plugin implementors, runs the body and returns the fallback if it panics, logging the panic and reporting it to the host through the fault flag
+// fn search(&self, query: RString) -> RVec<SearchResult> {
+//     guard_plugin_call!(self.fault, self.logger, "search", RVec::new(), { ... })
+// }
+#[macro_export]
+macro_rules! guard_plugin_call {
+    ($fault:expr, $logger:expr, $method:expr, $fallback:expr, $body:block) => {
+        $crate::catch_plugin_panic(&$logger, $method, || $body).unwrap_or_else(|error| {
+            $fault.set(error);
+            $fallback
+        })
+    };
+}
+
+// wraps a whole plugin so every trait method, including the ones the plugin leaves to the defaults, is guarded
+// extern "C" fn get_searchable(id: PluginId, logger: ScopedLogger, fault: FaultFlag) -> Searchable_TO<'static, RBox<()>> {
+//     Searchable_TO::from_value(GuardedSearchable::new(MyPlugin::new(), id, logger, fault), TD_Opaque)
+// }
+pub struct GuardedSearchable<S> {
+    inner: S,
+    // returned from plugin_id if the plugin panics in it
+    plugin_id: PluginId,
+    logger: ScopedLogger,
+    fault: FaultFlag,
+}
+
+impl<S: Searchable> GuardedSearchable<S> {
+    pub fn new(inner: S, plugin_id: PluginId, logger: ScopedLogger, fault: FaultFlag) -> Self {
+        Self { inner, plugin_id, logger, fault }
+    }
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+    fn guard<T>(&self, method: &str, fallback: impl FnOnce(&PluginError) -> T, f: impl FnOnce(&S) -> T) -> T {
+        catch_plugin_panic(&self.logger, method, || f(&self.inner)).unwrap_or_else(|error| {
+            let fallback = fallback(&error);
+            self.fault.set(error);
+            fallback
+        })
+    }
+}
+
+impl<S: Searchable> Searchable for GuardedSearchable<S> {
+    fn search(&self, query: RString) -> RVec<SearchResult> {
+        self.guard("search", |_| RVec::new(), |s| s.search(query))
+    }
+    fn search_cancellable(&self, query: RString, token: CancellationToken, sink: ResultSink) {
+        self.guard("search_cancellable", |_| (), |s| s.search_cancellable(query, token, sink))
+    }
+    fn search_query(&self, query: Query, token: CancellationToken, sink: ResultSink) {
+        self.guard("search_query", |_| (), |s| s.search_query(query, token, sink))
+    }
+    fn name(&self) -> RStr<'static> {
+        self.guard("name", |_| RStr::from_str(""), |s| s.name())
+    }
+    fn colored_name(&self) -> RVec<ColoredChar> {
+        self.guard("colored_name", |_| RVec::new(), |s| s.colored_name())
+    }
+    fn execute(&self, selected_result: &SearchResult) {
+        self.guard("execute", |_| (), |s| s.execute(selected_result))
+    }
+    fn execute_with_outcome(&self, selected_result: &SearchResult) -> ExecuteOutcome {
+        self.guard("execute_with_outcome", show_error, |s| s.execute_with_outcome(selected_result))
+    }
+    fn execute_action(&self, selected_result: &SearchResult, action_id: RString) -> ExecuteOutcome {
+        self.guard("execute_action", show_error, |s| s.execute_action(selected_result, action_id))
+    }
+    fn plugin_id(&self) -> PluginId {
+        self.guard("plugin_id", |_| self.plugin_id.clone(), |s| s.plugin_id())
+    }
+    fn lazy_load_config(&mut self, config: Config) {
+        let inner = &mut self.inner;
+        if let Err(error) = catch_plugin_panic(&self.logger, "lazy_load_config", || inner.lazy_load_config(config)) {
+            self.fault.set(error);
+        }
+    }
+    fn get_config_entries(&self) -> Config {
+        self.guard("get_config_entries", |_| Config::default(), |s| s.get_config_entries())
+    }
+    fn migrate_config(&self, config: Config, from_version: u32) -> Config {
+        let original = config.clone();
+        self.guard("migrate_config", |_| original, |s| s.migrate_config(config, from_version))
+    }
+    fn version(&self) -> RStr<'static> {
+        self.guard("version", |_| RStr::from_str(""), |s| s.version())
+    }
+    fn keywords(&self) -> RVec<RString> {
+        self.guard("keywords", |_| RVec::new(), |s| s.keywords())
+    }
+    fn cache_policy(&self) -> CachePolicy {
+        self.guard("cache_policy", |_| CachePolicy::default(), |s| s.cache_policy())
+    }
+}
+
+fn show_error(error: &PluginError) -> ExecuteOutcome {
+    ExecuteOutcome::ShowError {
+        message: error.to_string().into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{LogLevel, LogLevelOrCustom, Logger, SearchResult};
+
+    struct Panicky;
+
+    impl Searchable for Panicky {
+        fn search(&self, _: RString) -> RVec<SearchResult> {
+            panic!("search exploded")
+        }
+        fn name(&self) -> RStr<'static> {
+            RStr::from_str("panicky")
+        }
+        fn colored_name(&self) -> RVec<ColoredChar> {
+            RVec::new()
+        }
+        fn execute(&self, _: &SearchResult) {
+            panic!("execute exploded")
+        }
+        fn plugin_id(&self) -> PluginId {
+            PluginId { filename: "panicky".into() }
+        }
+        fn version(&self) -> RStr<'static> {
+            RStr::from_str("1.0.0")
+        }
+    }
+
+    fn guarded() -> (GuardedSearchable<Panicky>, FaultFlag) {
+        let logger = Logger::new(LogLevelOrCustom::from_min_level(LogLevel::Error), false);
+        let fault = FaultFlag::new();
+        (GuardedSearchable::new(Panicky, Panicky.plugin_id(), logger.new_scoped("panicky"), fault.clone()), fault)
+    }
+
+    #[test]
+    fn panics_are_reported_through_the_flag() {
+        let (plugin, fault) = guarded();
+        assert_eq!(plugin.name().as_str(), "panicky");
+        assert!(!fault.is_set());
+        assert!(plugin.search("a".into()).is_empty());
+        assert_eq!(
+            fault.get(),
+            Some(PluginError::Panicked {
+                method: "search".into(),
+                message: "search exploded".into()
+            })
+        );
+        fault.clear();
+        assert!(!fault.is_set());
+    }
+
+    #[test]
+    fn default_methods_are_guarded() {
+        let (plugin, fault) = guarded();
+        let (sink, stream) = ResultSink::channel();
+        plugin.search_query(Query::new("a"), CancellationToken::new(), sink);
+        assert!(stream.collect_all().is_empty());
+        assert!(matches!(fault.get(), Some(PluginError::Panicked { method, .. }) if method == "search_query"));
+        fault.clear();
+        assert!(matches!(
+            plugin.execute_action(&SearchResult::new("a"), crate::PRIMARY_ACTION.into()),
+            ExecuteOutcome::ShowError { .. }
+        ));
+        assert!(fault.is_set());
+    }
+}
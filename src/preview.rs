@@ -0,0 +1,46 @@
+// optional icon and preview pane contents for a SearchResult
+
+use abi_stable::{
+    std_types::{RString, RVec},
+    StableAbi,
+};
+use serde::{Deserialize, Serialize};
+
+#[repr(C)]
+#[derive(StableAbi, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ResultIcon {
+    Path { path: RString },
+    // encoded png file contents
+    Png { bytes: RVec<u8> },
+    // icon from the host's icon theme, e.g. "folder"
+    Named { name: RString },
+}
+
+#[repr(C)]
+#[derive(StableAbi, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Preview {
+    Text { text: RString },
+    Markdown { markdown: RString },
+    ImagePath { path: RString },
+    Table { rows: RVec<PreviewRow> },
+}
+
+#[repr(C)]
+#[derive(StableAbi, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PreviewRow {
+    pub key: RString,
+    pub value: RString,
+}
+
+impl<K, V> From<(K, V)> for PreviewRow
+where
+    K: Into<RString>,
+    V: Into<RString>,
+{
+    fn from((key, value): (K, V)) -> Self {
+        Self {
+            key: key.into(),
+            value: value.into(),
+        }
+    }
+}
@@ -0,0 +1,86 @@
+// structured query passed to plugins, carries more than just the typed text
+
+use abi_stable::{
+    std_types::{ROption, RString, RVec},
+    StableAbi,
+};
+use serde::{Deserialize, Serialize};
+
+#[repr(C)]
+#[derive(StableAbi, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Query {
+    // the text to search for, without the routing keyword if there was one
+    text: RString,
+    // text split on whitespace
+    terms: RVec<RString>,
+    // keyword that routed this query to the plugin, e.g. "calc" for "calc 2+2"
+    keyword: ROption<RString>,
+    // increases with every query the host sends, newer queries make older ones stale
+    generation: u64,
+    // plugins can stop searching once they have this many results
+    max_results: ROption<u32>,
+    // ui locale like "en-US", empty if unknown
+    locale: RString,
+}
+
+impl Query {
+    pub fn new(text: &str) -> Self {
+        Self {
+            text: text.into(),
+            terms: tokenize(text),
+            keyword: ROption::RNone,
+            generation: 0,
+            max_results: ROption::RNone,
+            locale: "".into(),
+        }
+    }
+    pub fn set_text(mut self, text: &str) -> Self {
+        self.text = text.into();
+        self.terms = tokenize(text);
+        self
+    }
+    pub fn set_keyword(mut self, keyword: Option<&str>) -> Self {
+        self.keyword = keyword.map(RString::from).into();
+        self
+    }
+    pub fn set_generation(mut self, generation: u64) -> Self {
+        self.generation = generation;
+        self
+    }
+    pub fn set_max_results(mut self, max_results: Option<u32>) -> Self {
+        self.max_results = max_results.into();
+        self
+    }
+    pub fn set_locale(mut self, locale: &str) -> Self {
+        self.locale = locale.into();
+        self
+    }
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+    pub fn terms(&self) -> &[RString] {
+        &self.terms
+    }
+    pub fn keyword(&self) -> Option<&str> {
+        self.keyword.as_ref().map(RString::as_str).into()
+    }
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+    pub fn max_results(&self) -> Option<u32> {
+        self.max_results.into()
+    }
+    pub fn locale(&self) -> &str {
+        &self.locale
+    }
+}
+
+impl From<&str> for Query {
+    fn from(text: &str) -> Self {
+        Self::new(text)
+    }
+}
+
+fn tokenize(text: &str) -> RVec<RString> {
+    text.split_whitespace().map(RString::from).collect()
+}
@@ -0,0 +1,152 @@
+// host side ranking, merges results from multiple plugins into a single ordered list
+// plugin scores are only comparable within a plugin so they are normalized per plugin before being weighted
+
+use std::{cmp::Ordering, collections::HashMap};
+
+use crate::{PluginId, SearchResult};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RankedResult {
+    pub plugin_id: PluginId,
+    pub result: SearchResult,
+    // normalized plugin score multiplied by the plugin weight, plus any boost
+    pub score: f64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Ranker {
+    // keyed by PluginId filename, plugins without an entry have a weight of 1.0
+    weights: HashMap<String, f64>,
+}
+
+impl Ranker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn set_weight(&mut self, plugin_id: &PluginId, weight: f64) {
+        self.weights.insert(plugin_id.filename.to_string(), weight);
+    }
+    pub fn remove_weight(&mut self, plugin_id: &PluginId) {
+        self.weights.remove(plugin_id.filename.as_ref());
+    }
+    pub fn weight(&self, plugin_id: &PluginId) -> f64 {
+        self.weights.get(plugin_id.filename.as_ref()).copied().unwrap_or(1.0)
+    }
+    // scales the scores of one plugin's results into 0.0..=1.0, if every score is the same they all get 1.0
+    pub fn normalize(results: &[SearchResult]) -> Vec<f64> {
+        let scores = results.iter().map(|r| if r.score().is_nan() { 0.0 } else { r.score() });
+        let (min, max) = scores.clone().fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), s| (min.min(s), max.max(s)));
+        let range = max - min;
+        scores.map(|s| if range > 0.0 && range.is_finite() { (s - min) / range } else { 1.0 }).collect()
+    }
+    // merges the results of every plugin, highest weighted score first
+    // equal scores keep each plugin's own order and are interleaved between plugins in the order they were given
+    pub fn rank(&self, batches: Vec<(PluginId, Vec<SearchResult>)>) -> Vec<RankedResult> {
+        self.rank_with_boost(batches, |_, _| 0.0)
+    }
+    // like rank but adds boost to each weighted score, e.g. FrecencyStore::boost
+    pub fn rank_with_boost(&self, batches: Vec<(PluginId, Vec<SearchResult>)>, boost: impl Fn(&PluginId, &SearchResult) -> f64) -> Vec<RankedResult> {
+        let mut ranked = Vec::new();
+        for (plugin_index, (plugin_id, results)) in batches.into_iter().enumerate() {
+            let weight = self.weight(&plugin_id);
+            let normalized = Self::normalize(&results);
+            for (result_index, (result, score)) in results.into_iter().zip(normalized).enumerate() {
+                let boost = boost(&plugin_id, &result);
+                ranked.push((
+                    result_index,
+                    plugin_index,
+                    RankedResult {
+                        plugin_id: plugin_id.clone(),
+                        result,
+                        score: score * weight + boost,
+                    },
+                ));
+            }
+        }
+        ranked.sort_by(|(a_index, a_plugin, a), (b_index, b_plugin, b)| {
+            b.score
+                .partial_cmp(&a.score)
+                .unwrap_or(Ordering::Equal)
+                .then(a_index.cmp(b_index))
+                .then(a_plugin.cmp(b_plugin))
+        });
+        ranked.into_iter().map(|(_, _, ranked)| ranked).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id(filename: &'static str) -> PluginId {
+        PluginId { filename: filename.into() }
+    }
+
+    fn titles(ranked: &[RankedResult]) -> Vec<&str> {
+        ranked.iter().map(|r| r.result.title()).collect()
+    }
+
+    #[test]
+    fn normalizes_per_plugin() {
+        let results = vec![
+            SearchResult::new("a").set_score(10.0),
+            SearchResult::new("b").set_score(20.0),
+            SearchResult::new("c").set_score(f64::NAN),
+        ];
+        assert_eq!(Ranker::normalize(&results), vec![0.5, 1.0, 0.0]);
+        let same = vec![SearchResult::new("a").set_score(3.0), SearchResult::new("b").set_score(3.0)];
+        assert_eq!(Ranker::normalize(&same), vec![1.0, 1.0]);
+    }
+
+    #[test]
+    fn ranks_by_normalized_score_not_raw_score() {
+        let ranked = Ranker::new().rank(vec![
+            (
+                id("big"),
+                vec![SearchResult::new("big low").set_score(100.0), SearchResult::new("big high").set_score(1000.0)],
+            ),
+            (
+                id("small"),
+                vec![
+                    SearchResult::new("small high").set_score(2.0),
+                    SearchResult::new("small mid").set_score(1.5),
+                    SearchResult::new("small low").set_score(1.0),
+                ],
+            ),
+        ]);
+        assert_eq!(titles(&ranked), vec!["small high", "big high", "small mid", "big low", "small low"]);
+    }
+
+    #[test]
+    fn weights_scale_plugin_scores() {
+        let mut ranker = Ranker::new();
+        ranker.set_weight(&id("b"), 2.0);
+        let ranked = ranker.rank(vec![(id("a"), vec![SearchResult::new("a1")]), (id("b"), vec![SearchResult::new("b1")])]);
+        assert_eq!(titles(&ranked), vec!["b1", "a1"]);
+        assert_eq!(ranked[0].score, 2.0);
+        ranker.remove_weight(&id("b"));
+        assert_eq!(ranker.weight(&id("b")), 1.0);
+    }
+
+    #[test]
+    fn ties_keep_plugin_order_and_interleave() {
+        let ranked = Ranker::new().rank(vec![
+            (id("a"), vec![SearchResult::new("a1"), SearchResult::new("a2")]),
+            (id("b"), vec![SearchResult::new("b1"), SearchResult::new("b2")]),
+        ]);
+        assert_eq!(titles(&ranked), vec!["a1", "b1", "a2", "b2"]);
+    }
+
+    #[test]
+    fn boost_is_added_after_weighting() {
+        let ranked = Ranker::new().rank_with_boost(vec![(id("a"), vec![SearchResult::new("a1"), SearchResult::new("a2")])], |_, r| {
+            if r.title() == "a2" {
+                0.5
+            } else {
+                0.0
+            }
+        });
+        assert_eq!(titles(&ranked), vec!["a2", "a1"]);
+        assert_eq!(ranked[0].score, 1.5);
+    }
+}
@@ -0,0 +1,346 @@
+// keeps track of every loaded plugin, finds them in plugin directories so the host doesnt have to load each one by hand
+// also reloads plugins when their file changes, the os hands back the image it already loaded for a path it has seen before
+// (always with the leaky-loader feature since the old library is never unloaded, and without it while a search still holds the old one)
+// so every plugin is loaded from a copy with a unique name in the cache directory
+// a plugin that fails to reload stays in the registry unloaded and is tried again on the next poll, e.g. while the compiler is still writing it
+// with the sandbox feature plugins can also be loaded into a child process, they are reloaded the same way they were first loaded
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::SystemTime,
+};
+
+use abi_stable::library::LibraryError;
+
+#[cfg(feature = "sandbox")]
+use crate::SandboxedLibrary;
+use crate::{migrate_and_repair, Config, Log, Logger, PluginId, PluginLibrary, SearchableLibrary, SharedFrecency};
+
+#[derive(Debug)]
+pub enum LoadError {
+    Library(LibraryError),
+    // reload was given an id that isnt in the registry
+    UnknownPlugin(PluginId),
+    // the plugin couldnt be copied into the cache directory
+    Copy(std::io::Error),
+    // the sandbox child couldnt be started or didnt answer
+    #[cfg(feature = "sandbox")]
+    Sandbox(anyhow::Error),
+}
+
+impl std::fmt::Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            LoadError::Library(e) => write!(f, "{}", e),
+            LoadError::UnknownPlugin(plugin_id) => write!(f, "no plugin {} in the registry", plugin_id.filename),
+            LoadError::Copy(e) => write!(f, "failed to copy the plugin into the cache directory: {}", e),
+            #[cfg(feature = "sandbox")]
+            LoadError::Sandbox(e) => write!(f, "{:#}", e),
+        }
+    }
+}
+
+impl From<LibraryError> for LoadError {
+    fn from(e: LibraryError) -> Self {
+        LoadError::Library(e)
+    }
+}
+
+#[derive(Debug)]
+pub struct PluginLoadError {
+    pub path: PathBuf,
+    pub error: LoadError,
+}
+
+impl std::fmt::Display for PluginLoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.error)
+    }
+}
+
+pub struct PluginRegistry {
+    plugins: Vec<LoadedPlugin>,
+    // handed to every plugin loaded through the registry
+    frecency: Option<SharedFrecency>,
+    // where the copies plugins are loaded from go
+    cache_dir: PathBuf,
+}
+
+impl Default for PluginRegistry {
+    fn default() -> Self {
+        Self {
+            plugins: Vec::new(),
+            frecency: None,
+            cache_dir: std::env::temp_dir().join("quick-search-plugins"),
+        }
+    }
+}
+
+// how a plugin was loaded, reloads use the same
+enum Loader {
+    Native,
+    // path of the helper binary that runs the sandbox child
+    #[cfg(feature = "sandbox")]
+    Sandboxed(PathBuf),
+}
+
+struct LoadedPlugin {
+    path: PathBuf,
+    loader: Loader,
+    // the id the plugin reported when it was last loaded
+    plugin_id: PluginId,
+    // arc so searches can keep running on other threads while the registry changes, None while a reload is failing
+    library: Option<Arc<dyn PluginLibrary>>,
+    // modification time of the file when it was loaded, to notice rebuilt plugins
+    modified: Option<SystemTime>,
+    // last config applied through the registry, applied again after a reload
+    config: Option<Config>,
+    // config was set while a search on another thread held the library, it is applied once the library is free
+    config_pending: bool,
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+// numbers the copies so no two loads in this process share a path
+static COPIES: AtomicUsize = AtomicUsize::new(0);
+
+// copies the plugin to "{stem}.{pid}.{n}.{ext}" in the cache directory
+fn copy_plugin(path: &Path, cache_dir: &Path) -> std::io::Result<PathBuf> {
+    fs::create_dir_all(cache_dir)?;
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let copy = cache_dir.join(format!(
+        "{}.{}.{}.{}",
+        stem,
+        std::process::id(),
+        COPIES.fetch_add(1, Ordering::Relaxed),
+        std::env::consts::DLL_EXTENSION
+    ));
+    fs::copy(path, &copy)?;
+    Ok(copy)
+}
+
+impl PluginRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    // the directory plugins are copied to before they are loaded, defaults to a directory in the system temp directory
+    pub fn set_cache_dir(&mut self, cache_dir: PathBuf) {
+        self.cache_dir = cache_dir;
+    }
+    // checks the extension against the platform dynamic library extension (so, dll, dylib)
+    pub fn is_plugin_file(path: &Path) -> bool {
+        path.is_file() && path.extension().is_some_and(|ext| ext == std::env::consts::DLL_EXTENSION)
+    }
+    // loads every plugin in the given directories, a plugin failing to load doesnt stop the rest from loading
+    pub fn scan<P: AsRef<Path>>(&mut self, dirs: &[P], logger: &Logger) -> Vec<PluginLoadError> {
+        let mut errors = Vec::new();
+        for dir in dirs {
+            let dir = dir.as_ref();
+            let entries = match fs::read_dir(dir) {
+                Ok(entries) => entries,
+                Err(e) => {
+                    logger.error(&format!("Failed to read plugin directory {}: {}", dir.display(), e));
+                    continue;
+                }
+            };
+            let mut paths: Vec<PathBuf> = entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| Self::is_plugin_file(path))
+                .collect();
+            paths.sort();
+            for path in paths {
+                if let Some(existing) = self.plugins.iter().find(|p| p.path.file_name() == path.file_name()) {
+                    logger.warn(&format!(
+                        "Skipping {}, a plugin with the same filename is already loaded from {}",
+                        path.display(),
+                        existing.path.display()
+                    ));
+                    continue;
+                }
+                if let Err(error) = self.load(path.clone(), logger) {
+                    logger.error(&format!("Failed to load plugin {}: {}", path.display(), error));
+                    errors.push(PluginLoadError { path, error });
+                }
+            }
+        }
+        errors
+    }
+    // loads the library, which checks its layout, with a scoped logger named after its PluginId
+    pub fn load(&mut self, path: PathBuf, logger: &Logger) -> Result<PluginId, LoadError> {
+        self.add(path, Loader::Native, logger)
+    }
+    // runs the plugin in a child process started from the helper binary, see SandboxedLibrary
+    #[cfg(feature = "sandbox")]
+    pub fn load_sandboxed(&mut self, path: PathBuf, helper: PathBuf, logger: &Logger) -> Result<PluginId, LoadError> {
+        self.add(path, Loader::Sandboxed(helper), logger)
+    }
+    fn add(&mut self, path: PathBuf, loader: Loader, logger: &Logger) -> Result<PluginId, LoadError> {
+        let (library, modified) = Self::load_library(path.clone(), &loader, &self.cache_dir, logger)?;
+        library.set_frecency(self.frecency.clone());
+        let plugin_id = library.plugin_id();
+        self.plugins.push(LoadedPlugin {
+            path,
+            loader,
+            plugin_id: plugin_id.clone(),
+            library: Some(Arc::from(library)),
+            modified,
+            config: None,
+            config_pending: false,
+        });
+        Ok(plugin_id)
+    }
+    fn load_library(path: PathBuf, loader: &Loader, cache_dir: &Path, logger: &Logger) -> Result<(Box<dyn PluginLibrary>, Option<SystemTime>), LoadError> {
+        let name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+        let modified = modified_time(&path);
+        let library: Box<dyn PluginLibrary> = match loader {
+            // SearchableLibrary checks the layout itself
+            Loader::Native => {
+                let copy = copy_plugin(&path, cache_dir).map_err(LoadError::Copy)?;
+                let library = SearchableLibrary::new_from(path, &copy, logger.new_scoped(&name));
+                // the loaded image stays mapped on unix, elsewhere the file is in use and stays behind
+                let _ = fs::remove_file(&copy);
+                Box::new(library?)
+            }
+            // the child checks the library itself
+            #[cfg(feature = "sandbox")]
+            Loader::Sandboxed(helper) => Box::new(SandboxedLibrary::with_helper(helper, path, logger.new_scoped(&name)).map_err(LoadError::Sandbox)?),
+        };
+        Ok((library, modified))
+    }
+    // drops the old library and loads the file again, reapplying the last config given to apply_config
+    // the config is migrated and repaired first since the new build may have changed its schema, like ConfigStore::load does
+    // if a search on another thread still holds the old library it is only dropped once that search is done
+    // if loading the new library fails the plugin stays in the registry unloaded and reload_changed keeps retrying it
+    pub fn reload(&mut self, plugin_id: &PluginId, logger: &Logger) -> Result<PluginId, LoadError> {
+        let index = self.index_of(plugin_id).ok_or_else(|| LoadError::UnknownPlugin(plugin_id.clone()))?;
+        let plugin = &mut self.plugins[index];
+        // drop the old library before loading the new one, SearchableLibrary takes care of the order within itself and a SandboxedLibrary kills its child
+        if let Some(old) = plugin.library.take() {
+            std::mem::drop(old);
+            logger.info(&format!("Reloading plugin {}", plugin.path.display()));
+        }
+        // remember the attempt so a file that stays broken is only reported again once it changes
+        plugin.modified = modified_time(&plugin.path);
+        let (mut library, modified) = Self::load_library(plugin.path.clone(), &plugin.loader, &self.cache_dir, logger)?;
+        if let Some(config) = plugin.config.take() {
+            let config = migrate_and_repair(&*library, config, logger);
+            library.lazy_load_config(config.clone());
+            plugin.config = Some(config);
+        }
+        library.set_frecency(self.frecency.clone());
+        plugin.plugin_id = library.plugin_id();
+        plugin.library = Some(Arc::from(library));
+        plugin.modified = modified;
+        plugin.config_pending = false;
+        Ok(plugin.plugin_id.clone())
+    }
+    // polls the modification time of every plugin file and reloads the ones that changed, plugins left unloaded by a failed reload are retried every time
+    // a retry that fails again is only logged and returned as an error if the file changed since the last attempt
+    // also applies configs left pending by apply_config
+    pub fn reload_changed(&mut self, logger: &Logger) -> (Vec<PluginId>, Vec<PluginLoadError>) {
+        let changed: Vec<(PluginId, PathBuf, bool)> = self
+            .plugins
+            .iter()
+            .filter_map(|p| {
+                let modified = modified_time(&p.path);
+                let is_changed = modified.is_some() && modified != p.modified;
+                (is_changed || p.library.is_none()).then(|| (p.plugin_id.clone(), p.path.clone(), is_changed))
+            })
+            .collect();
+        let mut reloaded = Vec::new();
+        let mut errors = Vec::new();
+        for (plugin_id, path, is_changed) in changed {
+            match self.reload(&plugin_id, logger) {
+                Ok(plugin_id) => reloaded.push(plugin_id),
+                Err(error) if is_changed => {
+                    logger.error(&format!("Failed to reload plugin {}: {}", path.display(), error));
+                    errors.push(PluginLoadError { path, error });
+                }
+                Err(_) => {}
+            }
+        }
+        self.apply_pending_configs();
+        (reloaded, errors)
+    }
+    // remembers the config for reloads and calls lazy_load_config, true if it was applied right away
+    // if a search on another thread still holds the plugin, or it is unloaded, the config stays pending
+    // and is applied by apply_pending_configs (which reload_changed calls) or the next reload, false if the plugin isnt in the registry either
+    pub fn apply_config(&mut self, plugin_id: &PluginId, config: Config) -> bool {
+        let Some(plugin) = self.plugins.iter_mut().find(|p| p.plugin_id == *plugin_id) else {
+            return false;
+        };
+        plugin.config = Some(config);
+        plugin.config_pending = true;
+        Self::apply_pending(plugin)
+    }
+    fn apply_pending(plugin: &mut LoadedPlugin) -> bool {
+        let (Some(library), Some(config)) = (plugin.library.as_mut().and_then(Arc::get_mut), &plugin.config) else {
+            return false;
+        };
+        library.lazy_load_config(config.clone());
+        plugin.config_pending = false;
+        true
+    }
+    // applies the configs that were set while their plugin was busy and returns the plugins they were applied to
+    pub fn apply_pending_configs(&mut self) -> Vec<PluginId> {
+        self.plugins
+            .iter_mut()
+            .filter(|p| p.config_pending)
+            .filter_map(|p| Self::apply_pending(p).then(|| p.plugin_id.clone()))
+            .collect()
+    }
+    pub fn is_config_pending(&self, plugin_id: &PluginId) -> bool {
+        self.plugins.iter().any(|p| p.plugin_id == *plugin_id && p.config_pending)
+    }
+    // the store every plugin records picks in when executed, including plugins loaded later
+    pub fn set_frecency(&mut self, frecency: Option<SharedFrecency>) {
+        for plugin in self.plugins() {
+            plugin.set_frecency(frecency.clone());
+        }
+        self.frecency = frecency;
+    }
+    pub fn config(&self, plugin_id: &PluginId) -> Option<&Config> {
+        self.plugins.iter().find(|p| p.plugin_id == *plugin_id).and_then(|p| p.config.as_ref())
+    }
+    fn index_of(&self, plugin_id: &PluginId) -> Option<usize> {
+        self.plugins.iter().position(|p| p.plugin_id == *plugin_id)
+    }
+    // only the plugins that are currently loaded
+    pub fn plugins(&self) -> impl Iterator<Item = &Arc<dyn PluginLibrary>> {
+        self.plugins.iter().filter_map(|p| p.library.as_ref())
+    }
+    // paths of the plugins whose last reload failed, they are retried by reload_changed
+    pub fn unloaded(&self) -> impl Iterator<Item = &Path> {
+        self.plugins.iter().filter(|p| p.library.is_none()).map(|p| p.path.as_path())
+    }
+    pub fn get(&self, plugin_id: &PluginId) -> Option<&Arc<dyn PluginLibrary>> {
+        self.plugins.iter().find(|p| p.plugin_id == *plugin_id).and_then(|p| p.library.as_ref())
+    }
+    // None if the plugin isnt loaded or a search on another thread is still using it
+    pub fn get_mut(&mut self, plugin_id: &PluginId) -> Option<&mut (dyn PluginLibrary + 'static)> {
+        self.plugins
+            .iter_mut()
+            .find(|p| p.plugin_id == *plugin_id)
+            .and_then(|p| p.library.as_mut())
+            .and_then(Arc::get_mut)
+    }
+    // also stops retrying an unloaded plugin, in which case there is no library to return
+    pub fn remove(&mut self, plugin_id: &PluginId) -> Option<Arc<dyn PluginLibrary>> {
+        let index = self.index_of(plugin_id)?;
+        self.plugins.remove(index).library
+    }
+    // number of loaded plugins, unloaded ones arent counted
+    pub fn len(&self) -> usize {
+        self.plugins().count()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
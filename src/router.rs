@@ -0,0 +1,91 @@
+// routes queries that start with a plugin keyword (e.g. "calc 2+2") to only that plugin
+
+use std::sync::Arc;
+
+use crate::{PluginId, PluginLibrary};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Route {
+    // the query started with a keyword, query is the rest of the text with the keyword stripped
+    Targeted { plugin_id: PluginId, keyword: String, query: String },
+    Global { query: String },
+}
+
+impl Route {
+    pub fn query(&self) -> &str {
+        match self {
+            Route::Targeted { query, .. } | Route::Global { query } => query,
+        }
+    }
+    pub fn plugin_id(&self) -> Option<&PluginId> {
+        match self {
+            Route::Targeted { plugin_id, .. } => Some(plugin_id),
+            Route::Global { .. } => None,
+        }
+    }
+}
+
+// the keyword only counts once it is followed by whitespace, so typing a longer word that starts with a keyword still searches everything
+// keywords are matched ignoring case, if two plugins share a keyword the first one wins
+pub fn route_query<'a, P: PluginLibrary + ?Sized + 'a>(plugins: impl IntoIterator<Item = &'a Arc<P>>, query: &str) -> Route {
+    let trimmed = query.trim_start();
+    if let Some((first, rest)) = trimmed.split_once(char::is_whitespace) {
+        for plugin in plugins {
+            if let Some(keyword) = plugin.keywords().into_iter().find(|k| k.eq_ignore_ascii_case(first)) {
+                return Route::Targeted {
+                    plugin_id: plugin.plugin_id(),
+                    keyword,
+                    query: rest.trim_start().to_owned(),
+                };
+            }
+        }
+    }
+    Route::Global { query: query.to_owned() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fake::FakePlugin;
+
+    fn plugins() -> Vec<Arc<FakePlugin>> {
+        vec![
+            Arc::new(FakePlugin::new("calc", &[]).set_keywords(&["calc", "="])),
+            Arc::new(FakePlugin::new("files", &[]).set_keywords(&["f"])),
+            Arc::new(FakePlugin::new("other calc", &[]).set_keywords(&["calc"])),
+        ]
+    }
+
+    fn targeted(plugin: &str, keyword: &str, query: &str) -> Route {
+        Route::Targeted {
+            plugin_id: PluginId {
+                filename: plugin.to_owned().into(),
+            },
+            keyword: keyword.into(),
+            query: query.into(),
+        }
+    }
+
+    #[test]
+    fn keywords_match_ignoring_case() {
+        assert_eq!(route_query(&plugins(), "CALC 2+2"), targeted("calc", "calc", "2+2"));
+        assert_eq!(route_query(&plugins(), "  F   notes.txt"), targeted("files", "f", "notes.txt"));
+        assert_eq!(route_query(&plugins(), "= 1"), targeted("calc", "=", "1"));
+    }
+
+    #[test]
+    fn keyword_needs_trailing_whitespace() {
+        assert_eq!(route_query(&plugins(), "calc"), Route::Global { query: "calc".into() });
+        assert_eq!(route_query(&plugins(), "calculator"), Route::Global { query: "calculator".into() });
+        assert_eq!(route_query(&plugins(), "fire fox"), Route::Global { query: "fire fox".into() });
+        assert_eq!(route_query(&plugins(), "calc "), targeted("calc", "calc", ""));
+    }
+
+    #[test]
+    fn first_plugin_wins_shared_keywords() {
+        let plugins = plugins();
+        assert_eq!(route_query(&plugins, "calc 1").plugin_id(), Some(&plugins[0].plugin_id()));
+        let reversed: Vec<_> = plugins.iter().rev().cloned().collect();
+        assert_eq!(route_query(&reversed, "calc 1").plugin_id(), Some(&plugins[2].plugin_id()));
+    }
+}
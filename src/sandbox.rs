@@ -0,0 +1,680 @@
+// runs a plugin in a child process so a crashing plugin only takes down the child instead of the whole launcher
+// the host re-runs its own executable (or a helper binary) with SANDBOX_ARG, which should call run_if_sandbox_child at the start of main
+// requests and responses are sent as json lines over the child's stdin and stdout, the child's logger also writes its log messages to stdout
+// and they are passed on to the host side ScopedLogger, secrets in configs are sent in plain text over the pipe
+// a child that exits or doesnt answer within the timeout is killed and started again on the next call, with the last config applied again
+
+use std::{
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    process::{Child, ChildStdin, Command, Stdio},
+    sync::{
+        mpsc::{self, RecvTimeoutError},
+        Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use abi_stable::std_types::RCowStr;
+use anyhow::{anyhow, bail, Context};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    record_pick, with_exposed_secrets, CachePolicy, CancellationToken, ColoredChar, Config, ExecuteOutcome, Log, LogLevelOrCustom, Logger, PluginError, PluginId, PluginLibrary,
+    Query, ResultSink, ScopedLogger, SearchResult, SearchableLibrary, SharedFrecency,
+};
+
+pub const SANDBOX_ARG: &str = "--quick-search-sandbox";
+
+// how long the child may stay silent before it is considered stuck and killed, log messages and result batches count as answers
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+// how often a running search checks whether its token was cancelled
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+#[derive(Serialize, Deserialize, Debug)]
+enum SandboxRequest {
+    Search { query: String },
+    // answered with any number of Batch responses followed by Done
+    SearchQuery { query: Query },
+    // stops the running SearchQuery, the only request the child handles while another one is running
+    Cancel,
+    Execute { result: SearchResult },
+    ExecuteAction { result: SearchResult, action_id: String },
+    LazyLoadConfig { config: Config },
+    GetConfigEntries,
+    MigrateConfig { config: Config, from_version: u32 },
+    Name,
+    ColoredName,
+    PluginId,
+    Version,
+    Keywords,
+    CachePolicy,
+    ClearFault,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+enum SandboxResponse {
+    Results(Vec<SearchResult>),
+    Batch(Vec<SearchResult>),
+    Outcome(ExecuteOutcome),
+    Config(Config),
+    Text(String),
+    Keywords(Vec<String>),
+    CachePolicy(CachePolicy),
+    ColoredName(Vec<ColoredChar>),
+    Done,
+    // the plugin faulted, sent instead of the response until the fault is cleared
+    Faulted(PluginError),
+    Error(String),
+}
+
+// call this first thing in main, if the process was started as a sandbox child it serves requests until the host closes stdin and exits
+// returns false for a normal launch
+pub fn run_if_sandbox_child() -> bool {
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) != Some(SANDBOX_ARG) {
+        return false;
+    }
+    let result = args
+        .get(2)
+        .zip(args.get(3))
+        .ok_or(anyhow!("usage: {} <plugin path> <log level>", SANDBOX_ARG))
+        .and_then(|(path, log_level)| serve(PathBuf::from(path), serde_json::from_str(log_level)?));
+    if let Err(e) = result {
+        eprintln!("Sandbox error: {:#}", e);
+        std::process::exit(1);
+    }
+    std::process::exit(0);
+}
+
+fn respond(response: &SandboxResponse) -> anyhow::Result<()> {
+    let mut stdout = std::io::stdout().lock();
+    writeln!(stdout, "{}", with_exposed_secrets(|| serde_json::to_string(response))?)?;
+    stdout.flush()?;
+    Ok(())
+}
+
+fn serve(path: PathBuf, log_level: LogLevelOrCustom) -> anyhow::Result<()> {
+    let logger = Logger::new(log_level, true);
+    let name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    let mut library = SearchableLibrary::new(path, logger.new_scoped(&name))?;
+    // stdin is read on its own thread so a Cancel gets through while a search is running
+    // the token is made when the SearchQuery is read, so a Cancel right behind it cant cancel the previous search instead
+    let (sender, requests) = mpsc::channel();
+    thread::spawn(move || {
+        let mut token = CancellationToken::new();
+        for line in std::io::stdin().lock().lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            let request = serde_json::from_str::<SandboxRequest>(&line);
+            match request {
+                Ok(SandboxRequest::Cancel) => {
+                    token.cancel();
+                    continue;
+                }
+                Ok(SandboxRequest::SearchQuery { .. }) => token = CancellationToken::new(),
+                _ => {}
+            }
+            if sender.send((request, token.clone())).is_err() {
+                break;
+            }
+        }
+    });
+    for (request, token) in requests {
+        let response = match request {
+            Ok(SandboxRequest::Search { query }) => SandboxResponse::Results(library.search(&query)),
+            Ok(SandboxRequest::SearchQuery { query }) => {
+                thread::scope(|scope| {
+                    for batch in library.search_stream(scope, &query, &token) {
+                        respond(&SandboxResponse::Batch(batch))?;
+                    }
+                    anyhow::Ok(())
+                })?;
+                SandboxResponse::Done
+            }
+            // handled by the stdin thread
+            Ok(SandboxRequest::Cancel) => SandboxResponse::Done,
+            Ok(SandboxRequest::Execute { result }) => SandboxResponse::Outcome(library.execute(&result)),
+            Ok(SandboxRequest::ExecuteAction { result, action_id }) => SandboxResponse::Outcome(library.execute_action(&result, &action_id)),
+            Ok(SandboxRequest::LazyLoadConfig { config }) => {
+                library.lazy_load_config(config);
+                SandboxResponse::Done
+            }
+            Ok(SandboxRequest::GetConfigEntries) => SandboxResponse::Config(library.get_config_entries()),
+            Ok(SandboxRequest::MigrateConfig { config, from_version }) => SandboxResponse::Config(library.migrate_config(config, from_version)),
+            Ok(SandboxRequest::Name) => SandboxResponse::Text(library.name().to_owned()),
+            Ok(SandboxRequest::ColoredName) => SandboxResponse::ColoredName(library.colored_name()),
+            Ok(SandboxRequest::PluginId) => SandboxResponse::Text(library.plugin_id().filename.to_string()),
+            Ok(SandboxRequest::Version) => SandboxResponse::Text(library.version().to_owned()),
+            Ok(SandboxRequest::Keywords) => SandboxResponse::Keywords(library.keywords()),
+            Ok(SandboxRequest::CachePolicy) => SandboxResponse::CachePolicy(library.cache_policy()),
+            Ok(SandboxRequest::ClearFault) => {
+                library.clear_fault();
+                SandboxResponse::Done
+            }
+            Err(e) => SandboxResponse::Error(format!("Invalid request: {}", e)),
+        };
+        let response = match library.fault() {
+            Some(fault) => SandboxResponse::Faulted(fault),
+            None => response,
+        };
+        respond(&response)?;
+        // the messages were already written to stdout, dont let them pile up in the channel
+        let _ = logger.get();
+    }
+    Ok(())
+}
+
+struct SandboxProcess {
+    child: Child,
+    stdin: ChildStdin,
+    // lines the child writes to stdout, read on their own thread so waiting for them can time out
+    lines: mpsc::Receiver<String>,
+}
+
+impl SandboxProcess {
+    fn spawn(helper: &Path, path: &Path, logger: &ScopedLogger) -> anyhow::Result<Self> {
+        let mut command = Command::new(helper);
+        command.arg(SANDBOX_ARG).arg(path).arg(serde_json::to_string(&logger.log_level())?);
+        Self::start(&mut command).with_context(|| format!("Failed to start sandbox for {}", path.display()))
+    }
+    fn start(command: &mut Command) -> anyhow::Result<Self> {
+        let mut child = command.stdin(Stdio::piped()).stdout(Stdio::piped()).spawn()?;
+        let stdin = child.stdin.take().ok_or(anyhow!("Sandbox has no stdin"))?;
+        let stdout = BufReader::new(child.stdout.take().ok_or(anyhow!("Sandbox has no stdout"))?);
+        let (sender, lines) = mpsc::channel();
+        // ends once the child exits and its stdout closes
+        thread::spawn(move || {
+            for line in stdout.lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Self { child, stdin, lines })
+    }
+    fn send(&mut self, request: &SandboxRequest) -> anyhow::Result<()> {
+        let request = with_exposed_secrets(|| serde_json::to_string(request))?;
+        writeln!(self.stdin, "{}", request).and_then(|_| self.stdin.flush()).context("Stopped accepting requests")
+    }
+    // sends the request and waits for the answer, log messages in between go to the logger and batches of a search to on_batch
+    // fails if the child exits or stays silent for longer than the timeout
+    fn exchange(
+        &mut self,
+        request: &SandboxRequest,
+        token: Option<&CancellationToken>,
+        timeout: Duration,
+        logger: &ScopedLogger,
+        mut on_batch: impl FnMut(Vec<SearchResult>),
+    ) -> anyhow::Result<SandboxResponse> {
+        self.send(request)?;
+        let mut cancel_sent = false;
+        let mut last_answer = Instant::now();
+        loop {
+            match self.lines.recv_timeout(CANCEL_POLL_INTERVAL) {
+                Ok(line) => {
+                    last_answer = Instant::now();
+                    match serde_json::from_str::<SandboxResponse>(&line) {
+                        Ok(SandboxResponse::Batch(batch)) => on_batch(batch),
+                        Ok(response) => return Ok(response),
+                        // anything else should be a log message from the child
+                        Err(_) => logger.import_deserialize(&line),
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    if !cancel_sent && token.is_some_and(CancellationToken::is_cancelled) {
+                        self.send(&SandboxRequest::Cancel)?;
+                        cancel_sent = true;
+                    }
+                    if last_answer.elapsed() >= timeout {
+                        bail!("Didnt answer within {:?}", timeout);
+                    }
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    let status = self.child.wait()?;
+                    bail!("Exited: {}", status);
+                }
+            }
+        }
+    }
+    fn kill(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+// keywords, cache policy and colored name can change with the config, so they are fetched whenever the child starts or gets a new config
+// reading them never waits on the child, which might be stuck in a search the dispatcher already gave up on
+#[derive(Default)]
+struct SandboxInfo {
+    keywords: Vec<String>,
+    cache_policy: CachePolicy,
+    colored_name: Vec<ColoredChar>,
+}
+
+// host side proxy for a plugin running in a child process
+// if the child crashes or hangs the call falls back like a faulted SearchableLibrary would, but the plugin isnt disabled and the next call restarts the child
+pub struct SandboxedLibrary {
+    helper: PathBuf,
+    path: PathBuf,
+    // None once the child exited or was killed
+    process: Mutex<Option<SandboxProcess>>,
+    logger: ScopedLogger,
+    timeout: Duration,
+    // last config given to lazy_load_config, applied again to a restarted child
+    config: Option<Config>,
+    // mirrors the fault reported by the child, the child's own flag is lost when it restarts
+    fault: Mutex<Option<PluginError>>,
+    frecency: Mutex<Option<SharedFrecency>>,
+    info: Mutex<SandboxInfo>,
+    // these never change so they are asked for once at startup
+    plugin_id: PluginId,
+    name: String,
+    version: String,
+}
+
+impl SandboxedLibrary {
+    // starts the current executable as the sandbox child
+    pub fn new(path: PathBuf, logger: ScopedLogger) -> anyhow::Result<Self> {
+        let helper = std::env::current_exe().context("Failed to find the current executable")?;
+        Self::with_helper(&helper, path, logger)
+    }
+    // starts the given helper binary as the sandbox child, it has to call run_if_sandbox_child
+    pub fn with_helper(helper: &Path, path: PathBuf, logger: ScopedLogger) -> anyhow::Result<Self> {
+        let mut library = Self {
+            helper: helper.to_owned(),
+            path,
+            process: Mutex::new(None),
+            logger,
+            timeout: DEFAULT_TIMEOUT,
+            config: None,
+            fault: Mutex::new(None),
+            frecency: Mutex::new(None),
+            info: Mutex::new(SandboxInfo::default()),
+            plugin_id: PluginId { filename: RCowStr::from("") },
+            name: String::new(),
+            version: String::new(),
+        };
+        let process = library.start()?;
+        *library.process.get_mut().unwrap_or_else(|e| e.into_inner()) = Some(process);
+        library.plugin_id = PluginId {
+            filename: library.text(SandboxRequest::PluginId)?.into(),
+        };
+        library.name = library.text(SandboxRequest::Name)?;
+        library.version = library.text(SandboxRequest::Version)?;
+        Ok(library)
+    }
+    // how long the child may stay silent before it is killed, a search has to send a batch or a log message at least this often
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+    fn text(&self, request: SandboxRequest) -> anyhow::Result<String> {
+        match self.exchange(request, None, |_| {})? {
+            SandboxResponse::Text(text) => Ok(text),
+            response => Err(unexpected(response)),
+        }
+    }
+    // runs f on the child, a child that isnt running is started first, one that f fails on is killed and left for the next call to restart
+    fn with_process<T>(&self, f: impl FnOnce(&mut SandboxProcess) -> anyhow::Result<T>) -> anyhow::Result<T> {
+        let mut process = self.process.lock().unwrap_or_else(|e| e.into_inner());
+        if process.is_none() {
+            self.logger.warn(&format!("Restarting sandbox for {}", self.path.display()));
+            *process = Some(self.start()?);
+        }
+        let Some(running) = process.as_mut() else {
+            bail!("Sandbox for {} isnt running", self.path.display());
+        };
+        let result = f(running).with_context(|| format!("Sandbox for {}", self.path.display()));
+        if result.is_err() {
+            running.kill();
+            *process = None;
+        }
+        result
+    }
+    fn exchange(&self, request: SandboxRequest, token: Option<&CancellationToken>, on_batch: impl FnMut(Vec<SearchResult>)) -> anyhow::Result<SandboxResponse> {
+        self.with_process(|process| process.exchange(&request, token, self.timeout, &self.logger, on_batch))
+    }
+    // spawns the child, applies the last config and fetches the info that depends on it
+    fn start(&self) -> anyhow::Result<SandboxProcess> {
+        let mut process = SandboxProcess::spawn(&self.helper, &self.path, &self.logger)?;
+        let result = match &self.config {
+            Some(config) => process
+                .exchange(&SandboxRequest::LazyLoadConfig { config: config.clone() }, None, self.timeout, &self.logger, |_| {})
+                .map(|_| ()),
+            None => Ok(()),
+        }
+        .and_then(|_| self.fetch_info(&mut process));
+        if let Err(e) = result {
+            process.kill();
+            return Err(e.context(format!("Sandbox for {}", self.path.display())));
+        }
+        Ok(process)
+    }
+    // a faulted plugin answers Faulted, its info falls back to the defaults then
+    fn fetch_info(&self, process: &mut SandboxProcess) -> anyhow::Result<()> {
+        let mut ask = |request: SandboxRequest| process.exchange(&request, None, self.timeout, &self.logger, |_| {});
+        let info = SandboxInfo {
+            keywords: match ask(SandboxRequest::Keywords)? {
+                SandboxResponse::Keywords(keywords) => keywords,
+                _ => Vec::new(),
+            },
+            cache_policy: match ask(SandboxRequest::CachePolicy)? {
+                SandboxResponse::CachePolicy(cache_policy) => cache_policy,
+                _ => CachePolicy::default(),
+            },
+            colored_name: match ask(SandboxRequest::ColoredName)? {
+                SandboxResponse::ColoredName(colored_name) => colored_name,
+                _ => Vec::new(),
+            },
+        };
+        *self.info.lock().unwrap_or_else(|e| e.into_inner()) = info;
+        Ok(())
+    }
+    fn info<T>(&self, f: impl FnOnce(&SandboxInfo) -> T) -> T {
+        f(&self.info.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+    // like SearchableLibrary::call, skips the call once the plugin faulted and records faults the child reports
+    fn call<T>(
+        &self,
+        method: &str,
+        request: SandboxRequest,
+        token: Option<&CancellationToken>,
+        on_batch: impl FnMut(Vec<SearchResult>),
+        extract: impl FnOnce(SandboxResponse) -> Option<T>,
+    ) -> Result<T, PluginError> {
+        if self.is_faulted() {
+            return Err(PluginError::Disabled { method: method.into() });
+        }
+        let unavailable = |message: String| {
+            self.logger.error(&format!("Sandboxed plugin call {} failed: {}", method, message));
+            PluginError::Unavailable {
+                method: method.into(),
+                message: message.into(),
+            }
+        };
+        match self.exchange(request, token, on_batch) {
+            Ok(SandboxResponse::Faulted(fault)) => {
+                *self.fault.lock().unwrap_or_else(|e| e.into_inner()) = Some(fault.clone());
+                Err(fault)
+            }
+            Ok(SandboxResponse::Error(message)) => Err(unavailable(message)),
+            Ok(response) => {
+                let description = format!("{:?}", response);
+                extract(response).ok_or_else(|| unavailable(format!("Unexpected response from sandbox: {}", description)))
+            }
+            Err(e) => Err(unavailable(format!("{:#}", e))),
+        }
+    }
+    fn simple_call<T>(&self, method: &str, request: SandboxRequest, extract: impl FnOnce(SandboxResponse) -> Option<T>) -> Result<T, PluginError> {
+        self.call(method, request, None, |_| {}, extract)
+    }
+}
+
+fn unexpected(response: SandboxResponse) -> anyhow::Error {
+    anyhow!("Unexpected response from sandbox: {:?}", response)
+}
+
+fn execute_error(error: PluginError) -> ExecuteOutcome {
+    ExecuteOutcome::ShowError {
+        message: error.to_string().into(),
+    }
+}
+
+impl PluginLibrary for SandboxedLibrary {
+    fn search(&self, query: &str) -> Vec<SearchResult> {
+        self.simple_call("search", SandboxRequest::Search { query: query.to_owned() }, |response| match response {
+            SandboxResponse::Results(results) => Some(results),
+            _ => None,
+        })
+        .unwrap_or_default()
+    }
+    fn search_into(&self, query: &Query, token: &CancellationToken, sink: ResultSink) {
+        let request = SandboxRequest::SearchQuery { query: query.clone() };
+        let _ = self.call(
+            "search_query",
+            request,
+            Some(token),
+            |batch| sink.send_batch(batch.into()),
+            |response| matches!(response, SandboxResponse::Done).then_some(()),
+        );
+    }
+    fn name(&self) -> &str {
+        &self.name
+    }
+    fn colored_name(&self) -> Vec<ColoredChar> {
+        if self.is_faulted() {
+            return Vec::new();
+        }
+        self.info(|info| info.colored_name.clone())
+    }
+    fn execute(&self, selected_result: &SearchResult) -> ExecuteOutcome {
+        record_pick(self, selected_result);
+        self.simple_call("execute", SandboxRequest::Execute { result: selected_result.clone() }, |response| match response {
+            SandboxResponse::Outcome(outcome) => Some(outcome),
+            _ => None,
+        })
+        .unwrap_or_else(execute_error)
+    }
+    fn execute_action(&self, selected_result: &SearchResult, action_id: &str) -> ExecuteOutcome {
+        record_pick(self, selected_result);
+        let request = SandboxRequest::ExecuteAction {
+            result: selected_result.clone(),
+            action_id: action_id.to_owned(),
+        };
+        self.simple_call("execute_action", request, |response| match response {
+            SandboxResponse::Outcome(outcome) => Some(outcome),
+            _ => None,
+        })
+        .unwrap_or_else(execute_error)
+    }
+    fn plugin_id(&self) -> PluginId {
+        self.plugin_id.clone()
+    }
+    fn lazy_load_config(&mut self, config: Config) {
+        self.config = Some(config.clone());
+        if self
+            .simple_call("lazy_load_config", SandboxRequest::LazyLoadConfig { config }, |response| {
+                matches!(response, SandboxResponse::Done).then_some(())
+            })
+            .is_err()
+        {
+            return;
+        }
+        if let Err(e) = self.with_process(|process| self.fetch_info(process)) {
+            self.logger.error(&format!("{:#}", e));
+        }
+    }
+    fn get_config_entries(&self) -> Config {
+        self.simple_call("get_config_entries", SandboxRequest::GetConfigEntries, |response| match response {
+            SandboxResponse::Config(config) => Some(config),
+            _ => None,
+        })
+        .unwrap_or_default()
+    }
+    fn migrate_config(&self, config: Config, from_version: u32) -> Config {
+        let original = config.clone();
+        self.simple_call("migrate_config", SandboxRequest::MigrateConfig { config, from_version }, |response| match response {
+            SandboxResponse::Config(config) => Some(config),
+            _ => None,
+        })
+        .unwrap_or(original)
+    }
+    fn version(&self) -> &str {
+        &self.version
+    }
+    fn keywords(&self) -> Vec<String> {
+        if self.is_faulted() {
+            return Vec::new();
+        }
+        self.info(|info| info.keywords.clone())
+    }
+    fn cache_policy(&self) -> CachePolicy {
+        if self.is_faulted() {
+            return CachePolicy::default();
+        }
+        self.info(|info| info.cache_policy)
+    }
+    fn path(&self) -> &Path {
+        &self.path
+    }
+    fn fault(&self) -> Option<PluginError> {
+        self.fault.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+    fn clear_fault(&self) {
+        *self.fault.lock().unwrap_or_else(|e| e.into_inner()) = None;
+        let _ = self.simple_call("clear_fault", SandboxRequest::ClearFault, |response| {
+            matches!(response, SandboxResponse::Done).then_some(())
+        });
+    }
+    fn set_frecency(&self, frecency: Option<SharedFrecency>) {
+        *self.frecency.lock().unwrap_or_else(|e| e.into_inner()) = frecency;
+    }
+    fn frecency(&self) -> Option<SharedFrecency> {
+        self.frecency.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
+impl Drop for SandboxedLibrary {
+    fn drop(&mut self) {
+        #[cfg(feature = "debug")]
+        eprintln!("Dropping SandboxedLibrary: {:?}", self.path);
+        // closing stdin would end the child's loop but a stuck plugin would never notice
+        if let Some(process) = self.process.get_mut().unwrap_or_else(|e| e.into_inner()) {
+            process.kill();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use abi_stable::std_types::ROption;
+
+    use crate::{EntryType, LogLevel, SecretString};
+
+    fn logger() -> Logger {
+        Logger::new(
+            LogLevelOrCustom::from_levels(&[LogLevel::Trace, LogLevel::Debug, LogLevel::Info, LogLevel::Warn, LogLevel::Error]),
+            false,
+        )
+    }
+
+    fn secret_config() -> Config {
+        let mut config = Config::new().with_version(2);
+        config.insert(
+            "token".into(),
+            EntryType::Secret {
+                value: SecretString::new("hunter2"),
+            },
+        );
+        config
+    }
+
+    fn token_of(request: SandboxRequest) -> String {
+        match request {
+            SandboxRequest::LazyLoadConfig { config } => config.get("token").and_then(EntryType::as_secret).unwrap().expose().to_owned(),
+            request => panic!("unexpected request {:?}", request),
+        }
+    }
+
+    #[test]
+    fn requests_carry_secrets_only_when_exposed() {
+        let request = SandboxRequest::LazyLoadConfig { config: secret_config() };
+        let exposed = with_exposed_secrets(|| serde_json::to_string(&request)).unwrap();
+        assert_eq!(token_of(serde_json::from_str(&exposed).unwrap()), "hunter2");
+        let masked = serde_json::to_string(&request).unwrap();
+        assert!(!masked.contains("hunter2"));
+        assert_eq!(token_of(serde_json::from_str(&masked).unwrap()), "");
+
+        let request = SandboxRequest::SearchQuery { query: Query::new("hello") };
+        match serde_json::from_str(&serde_json::to_string(&request).unwrap()).unwrap() {
+            SandboxRequest::SearchQuery { query } => assert_eq!(query, Query::new("hello")),
+            request => panic!("unexpected request {:?}", request),
+        }
+    }
+
+    #[test]
+    fn non_finite_floats_survive_responses() {
+        let batch = SandboxResponse::Batch(vec![
+            SearchResult::new("nan").set_score(f64::NAN),
+            SearchResult::new("inf").set_score(f64::INFINITY),
+            SearchResult::new("one").set_score(1.0),
+        ]);
+        let line = serde_json::to_string(&batch).unwrap();
+        let Ok(SandboxResponse::Batch(batch)) = serde_json::from_str(&line) else {
+            panic!("batch didnt round trip: {}", line);
+        };
+        assert!(batch[0].score().is_nan());
+        assert_eq!(batch[1].score(), f64::INFINITY);
+        assert_eq!(batch[2].score(), 1.0);
+
+        let mut config = Config::new();
+        config.insert(
+            "ratio".into(),
+            EntryType::Float {
+                value: f64::NAN,
+                min: ROption::RSome(f64::NEG_INFINITY),
+                max: ROption::RNone,
+            },
+        );
+        let line = serde_json::to_string(&SandboxResponse::Config(config)).unwrap();
+        let Ok(SandboxResponse::Config(config)) = serde_json::from_str(&line) else {
+            panic!("config didnt round trip: {}", line);
+        };
+        let Some(EntryType::Float { value, min, max }) = config.get("ratio") else {
+            panic!("ratio is missing");
+        };
+        assert!(value.is_nan());
+        assert_eq!(*min, ROption::RSome(f64::NEG_INFINITY));
+        assert_eq!(*max, ROption::RNone);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn exchange_tells_log_lines_from_responses() {
+        let child_logger = logger();
+        child_logger.new_scoped("child").info("hello from the child");
+        let log_line = serde_json::to_string(&child_logger.get().remove(0)).unwrap();
+        let batch = serde_json::to_string(&SandboxResponse::Batch(vec![SearchResult::new("a")])).unwrap();
+        let done = serde_json::to_string(&SandboxResponse::Done).unwrap();
+
+        let mut command = Command::new("sh");
+        command
+            .arg("-c")
+            .arg(r#"read request; printf '%s\n%s\n%s\n' "$LOG_LINE" "$BATCH" "$DONE"; read request"#)
+            .env("LOG_LINE", &log_line)
+            .env("BATCH", &batch)
+            .env("DONE", &done);
+        let mut process = SandboxProcess::start(&mut command).unwrap();
+        let logger = logger();
+        let mut batches = Vec::new();
+        let request = SandboxRequest::SearchQuery { query: Query::new("a") };
+        let response = process
+            .exchange(&request, None, Duration::from_secs(5), &logger.new_scoped("host"), |batch| batches.push(batch))
+            .unwrap();
+        process.kill();
+
+        assert!(matches!(response, SandboxResponse::Done));
+        assert_eq!(batches, vec![vec![SearchResult::new("a")]]);
+        let messages = logger.get();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].message.as_str(), "hello from the child");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn exchange_times_out_on_a_silent_child() {
+        let mut process = SandboxProcess::start(Command::new("sh").arg("-c").arg("read request; sleep 5")).unwrap();
+        let logger = logger();
+        let started = Instant::now();
+        let result = process.exchange(&SandboxRequest::Name, None, Duration::from_millis(100), &logger.new_scoped("host"), |_| {});
+        process.kill();
+        assert!(result.is_err());
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+}
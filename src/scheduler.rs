@@ -0,0 +1,141 @@
+// host side query scheduler, debounces keystrokes so only the query the user stopped typing at gets searched
+// every submitted query gets a new generation, submitting cancels the search for the previous one
+// and results coming back for an older generation are dropped so they never replace fresher ones
+
+use std::time::{Duration, Instant};
+
+use crate::{CancellationToken, Query};
+
+#[derive(Debug, Clone)]
+pub struct ScheduledQuery {
+    // has the generation set
+    pub query: Query,
+    // pass this to SearchEngine::search_cancellable, it is cancelled once a newer query is submitted
+    pub token: CancellationToken,
+}
+
+pub struct QueryScheduler {
+    debounce: Duration,
+    generation: u64,
+    // text and time of the last submit that hasnt been handed out by poll yet
+    pending: Option<(String, Instant)>,
+    in_flight: Option<CancellationToken>,
+}
+
+impl QueryScheduler {
+    pub fn new(debounce: Duration) -> Self {
+        Self {
+            debounce,
+            generation: 0,
+            pending: None,
+            in_flight: None,
+        }
+    }
+    // call on every change of the query text, returns the generation of the new query
+    pub fn submit(&mut self, text: &str) -> u64 {
+        self.generation += 1;
+        self.pending = Some((text.to_owned(), Instant::now()));
+        if let Some(token) = self.in_flight.take() {
+            token.cancel();
+        }
+        self.generation
+    }
+    // the pending query once the user has stopped typing for the debounce time
+    pub fn poll(&mut self) -> Option<ScheduledQuery> {
+        match &self.pending {
+            Some((_, submitted)) if submitted.elapsed() >= self.debounce => self.flush(),
+            _ => None,
+        }
+    }
+    // the pending query right away, e.g. when the user presses enter
+    pub fn flush(&mut self) -> Option<ScheduledQuery> {
+        let (text, _) = self.pending.take()?;
+        let token = CancellationToken::new();
+        self.in_flight = Some(token.clone());
+        Some(ScheduledQuery {
+            query: Query::new(&text).set_generation(self.generation),
+            token,
+        })
+    }
+    // how long the host can sleep before poll will return the pending query, None if nothing is pending
+    pub fn time_until_ready(&self) -> Option<Duration> {
+        self.pending.as_ref().map(|(_, submitted)| self.debounce.saturating_sub(submitted.elapsed()))
+    }
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+    pub fn is_current(&self, generation: u64) -> bool {
+        generation == self.generation
+    }
+    // passes the results through if they belong to the latest query, None if a newer query was submitted since
+    pub fn accept<T>(&mut self, generation: u64, results: T) -> Option<T> {
+        if !self.is_current(generation) {
+            return None;
+        }
+        self.in_flight = None;
+        Some(results)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn waits_until_typing_stops() {
+        let mut scheduler = QueryScheduler::new(Duration::from_millis(200));
+        assert!(scheduler.poll().is_none());
+        assert_eq!(scheduler.time_until_ready(), None);
+
+        scheduler.submit("a");
+        std::thread::sleep(Duration::from_millis(120));
+        assert!(scheduler.poll().is_none());
+        // typing again restarts the wait
+        scheduler.submit("ab");
+        std::thread::sleep(Duration::from_millis(120));
+        assert!(scheduler.poll().is_none());
+        assert!(scheduler.time_until_ready().is_some_and(|wait| wait <= Duration::from_millis(80)));
+
+        std::thread::sleep(scheduler.time_until_ready().unwrap());
+        let scheduled = scheduler.poll().unwrap();
+        assert_eq!(scheduled.query.text(), "ab");
+        assert_eq!(scheduled.query.generation(), 2);
+        assert!(scheduler.poll().is_none());
+    }
+
+    #[test]
+    fn flush_skips_the_wait() {
+        let mut scheduler = QueryScheduler::new(Duration::from_secs(60));
+        assert!(scheduler.flush().is_none());
+        scheduler.submit("now");
+        assert_eq!(scheduler.flush().unwrap().query.text(), "now");
+        assert!(scheduler.flush().is_none());
+    }
+
+    #[test]
+    fn submit_cancels_the_search_in_flight() {
+        let mut scheduler = QueryScheduler::new(Duration::ZERO);
+        scheduler.submit("a");
+        let first = scheduler.flush().unwrap();
+        assert!(!first.token.is_cancelled());
+        scheduler.submit("ab");
+        assert!(first.token.is_cancelled());
+
+        // a search whose results were accepted is done, the next submit leaves its token alone
+        let second = scheduler.flush().unwrap();
+        assert_eq!(scheduler.accept(second.query.generation(), ()), Some(()));
+        scheduler.submit("abc");
+        assert!(!second.token.is_cancelled());
+    }
+
+    #[test]
+    fn accept_drops_superseded_generations() {
+        let mut scheduler = QueryScheduler::new(Duration::ZERO);
+        let first = scheduler.submit("a");
+        let second = scheduler.submit("ab");
+        assert_eq!(second, first + 1);
+        assert!(!scheduler.is_current(first));
+        assert_eq!(scheduler.accept(first, vec!["old"]), None);
+        assert_eq!(scheduler.accept(second, vec!["new"]), Some(vec!["new"]));
+    }
+}
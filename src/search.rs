@@ -0,0 +1,120 @@
+// cancellable, streaming search support, lets the host render results as soon as a plugin finds them and stop a plugin mid-search
+
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use abi_stable::{
+    external_types::crossbeam_channel::{self, RReceiver, RSender},
+    std_types::{RArc, RVec},
+    StableAbi,
+};
+
+use crate::SearchResult;
+
+// shared flag between the host and a running search, cloning it gives another handle to the same flag
+#[repr(C)]
+#[derive(StableAbi, Clone, Debug)]
+pub struct CancellationToken {
+    // the last flag is this token's own, the ones before it belong to its parents
+    flags: RVec<RArc<AtomicBool>>,
+}
+
+impl Default for CancellationToken {
+    fn default() -> Self {
+        Self {
+            flags: RVec::from(vec![RArc::new(AtomicBool::new(false))]),
+        }
+    }
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    // a token that is cancelled along with this one but can also be cancelled on its own
+    pub fn child(&self) -> Self {
+        let mut flags = self.flags.clone();
+        flags.push(RArc::new(AtomicBool::new(false)));
+        Self { flags }
+    }
+    pub fn cancel(&self) {
+        if let Some(flag) = self.flags.last() {
+            flag.store(true, Ordering::Release);
+        }
+    }
+    pub fn is_cancelled(&self) -> bool {
+        self.flags.iter().any(|flag| flag.load(Ordering::Acquire))
+    }
+}
+
+// plugin side of a result channel, plugins push batches as they find them
+// the stream on the host side ends once every clone of the sink is dropped
+#[repr(C)]
+#[derive(StableAbi, Clone)]
+pub struct ResultSink {
+    sender: RSender<RVec<SearchResult>>,
+}
+
+impl ResultSink {
+    pub fn channel() -> (ResultSink, ResultStream) {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        (ResultSink { sender }, ResultStream { receiver })
+    }
+    pub fn push(&self, result: SearchResult) {
+        self.send_batch(RVec::from(vec![result]));
+    }
+    pub fn send_batch(&self, results: RVec<SearchResult>) {
+        if results.is_empty() {
+            return;
+        }
+        // the host stopped listening, nothing useful to do with the results
+        let _ = self.sender.send(results);
+    }
+}
+
+// host side of a result channel, iterating yields batches in the order the plugin sent them
+#[repr(C)]
+#[derive(StableAbi)]
+pub struct ResultStream {
+    receiver: RReceiver<RVec<SearchResult>>,
+}
+
+impl ResultStream {
+    // blocks until the next batch arrives, None once the plugin is done
+    pub fn recv_batch(&self) -> Option<Vec<SearchResult>> {
+        self.receiver.recv().ok().map(Into::into)
+    }
+    pub fn try_recv_batch(&self) -> Option<Vec<SearchResult>> {
+        self.receiver.try_recv().ok().map(Into::into)
+    }
+    pub fn recv_batch_timeout(&self, timeout: Duration) -> StreamEvent {
+        match self.receiver.recv_timeout(timeout) {
+            Ok(batch) => StreamEvent::Batch(batch.into()),
+            Err(e) if e.is_timeout() => StreamEvent::Timeout,
+            Err(_) => StreamEvent::Done,
+        }
+    }
+    // blocks until the plugin is done and returns everything it sent
+    pub fn collect_all(self) -> Vec<SearchResult> {
+        self.flatten().collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StreamEvent {
+    Batch(Vec<SearchResult>),
+    // nothing arrived in time, the plugin is still running
+    Timeout,
+    // the plugin finished and dropped its sink
+    Done,
+}
+
+impl Iterator for ResultStream {
+    type Item = Vec<SearchResult>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.recv_batch()
+    }
+}